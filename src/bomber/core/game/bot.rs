@@ -0,0 +1,414 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::game::{Action, Game};
+use crate::bomber::core::server::GameStream;
+use crate::bomber::gen::Map;
+use crate::bomber::gen::item::Walkable;
+use crate::bomber::gen::utils::{Direction, Shape, SquareType};
+
+// Squares threatened by a bomb
+pub type DangerMap = HashSet<(usize, usize)>;
+
+// Max squares a bot accepts to run to escape its own bomb
+const MAX_ESCAPE: usize = 6;
+// An opponent unable to escape in this number of squares is trapped
+const TRAP_ESCAPE: usize = 2;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /**
+     * @return the delay between two decisions in milliseconds
+     */
+    fn reaction_time(&self) -> u64 {
+        match self {
+            Difficulty::Easy => 300,
+            Difficulty::Normal => 120,
+            Difficulty::Hard => 0,
+        }
+    }
+
+    /**
+     * @return the probability to do a random move instead of a decision
+     */
+    fn mistakes(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.05,
+            Difficulty::Hard => 0.,
+        }
+    }
+}
+
+/**
+ * A player driven by the server. The bot reads the game state and produces
+ * one action per tick, like a client would do.
+ */
+pub struct Bot {
+    gid: u64,
+    difficulty: Difficulty,
+    rx: GameStream,
+    rng: StdRng,
+    path: VecDeque<(usize, usize)>,
+    next_decision: u64,
+}
+
+/**
+ * Get the squares reached by a bomb, as computed by the game during the explosion
+ * @param map       The map
+ * @param pos       The position of the bomb
+ * @param radius    The radius of the bomb
+ * @param shape     The shape of the bomb
+ * @return          The squares reached
+ */
+pub fn blast(map: &Map, pos: (usize, usize), radius: i32, shape: Shape) -> DangerMap {
+    let mut reached = HashSet::new();
+    let mut blocked_pos = HashSet::new();
+    for r in 0..(radius + 1) {
+        for offset in shape.ring(r) {
+            let x = pos.0 as i32 + offset.0;
+            let y = pos.1 as i32 + offset.1;
+            if x < 0 || x >= map.w as i32 || y < 0 || y >= map.h as i32 {
+                continue;
+            }
+            let blocked = Shape::path(&offset).iter().any(|p| {
+                blocked_pos.contains(&(pos.0 as i32 + p.0, pos.1 as i32 + p.1))
+            });
+            if blocked {
+                continue;
+            }
+            let square = (x as usize, y as usize);
+            let idx = square.0 + square.1 * map.w;
+            let (mut blocked, clear) = map.squares[idx].sq_type.explode_event(&square, &pos);
+            if !blocked {
+                if let Some(item) = &map.items[idx] {
+                    blocked = item.explode_event(&square, &pos).0;
+                }
+            }
+            if clear {
+                reached.insert(square);
+            }
+            if blocked {
+                blocked_pos.insert((x, y));
+            }
+        }
+    }
+    reached
+}
+
+/**
+ * @param game  The game
+ * @return      The squares which will be reached by the current bombs
+ */
+pub fn danger_map(game: &Game) -> DangerMap {
+    let mut danger = HashSet::new();
+    for bomb in &game.bombs {
+        let pos = (bomb.pos.0 as usize, bomb.pos.1 as usize);
+        danger.extend(blast(&game.map, pos, bomb.radius as i32, bomb.shape));
+    }
+    danger
+}
+
+/**
+ * @return if a player can walk on a square (walls are avoided)
+ */
+fn walkable(map: &Map, pos: (usize, usize)) -> bool {
+    let idx = pos.0 + pos.1 * map.w;
+    if map.squares[idx].sq_type != SquareType::Empty {
+        return false;
+    }
+    match &map.items[idx] {
+        Some(item) => item.name() == "Bonus" || item.name() == "Malus",
+        None => true,
+    }
+}
+
+fn neighbours(map: &Map, pos: (usize, usize)) -> Vec<((usize, usize), Direction)> {
+    let mut res = Vec::new();
+    if pos.1 > 0 {
+        res.push(((pos.0, pos.1 - 1), Direction::North));
+    }
+    if pos.1 + 1 < map.h {
+        res.push(((pos.0, pos.1 + 1), Direction::South));
+    }
+    if pos.0 > 0 {
+        res.push(((pos.0 - 1, pos.1), Direction::West));
+    }
+    if pos.0 + 1 < map.w {
+        res.push(((pos.0 + 1, pos.1), Direction::East));
+    }
+    res
+}
+
+/**
+ * Search the nearest square matching a goal
+ * @param map       The map
+ * @param start     The starting square (always walkable)
+ * @param avoid     Squares to not walk on
+ * @param max_len   Maximum length of the path
+ * @param goal      The goal to reach
+ * @return          The path to the goal, without the starting square
+ */
+fn bfs<F>(map: &Map, start: (usize, usize), avoid: &DangerMap, max_len: usize, goal: F)
+    -> Option<VecDeque<(usize, usize)>>
+    where F: Fn((usize, usize)) -> bool
+{
+    let mut parents = HashMap::new();
+    let mut to_check = VecDeque::new();
+    parents.insert(start, start);
+    to_check.push_back((start, 0));
+    while let Some((current, len)) = to_check.pop_front() {
+        if goal(current) {
+            let mut path = VecDeque::new();
+            let mut pos = current;
+            while pos != start {
+                path.push_front(pos);
+                pos = parents[&pos];
+            }
+            return Some(path);
+        }
+        if len == max_len {
+            continue;
+        }
+        for (next, _) in neighbours(map, current) {
+            if parents.contains_key(&next) || avoid.contains(&next) || !walkable(map, next) {
+                continue;
+            }
+            parents.insert(next, current);
+            to_check.push_back((next, len + 1));
+        }
+    }
+    None
+}
+
+impl Bot {
+    /**
+     * Create a bot
+     * @param gid           The id of the bot in the game
+     * @param difficulty    The difficulty of the bot
+     * @param rx            The game stream linked to the game
+     * @param seed          Seed of the random generator of the bot
+     * @return              The created Bot
+     */
+    pub fn new(gid: u64, difficulty: Difficulty, rx: GameStream, seed: u64) -> Bot {
+        Bot {
+            gid,
+            difficulty,
+            rx,
+            rng: StdRng::seed_from_u64(seed),
+            path: VecDeque::new(),
+            next_decision: 0,
+        }
+    }
+
+    /**
+     * @return the id of the bot in the game
+     */
+    pub fn gid(&self) -> u64 {
+        self.gid
+    }
+
+    /**
+     * Forget the current plan, used when a new game starts
+     */
+    pub fn reset(&mut self) {
+        self.path.clear();
+        self.next_decision = 0;
+    }
+
+    /**
+     * Choose the action of the bot for the current tick
+     * @param game      The game
+     * @param danger    The danger map of the game
+     * @return          The action to push, if any
+     */
+    pub fn think(&mut self, game: &Game, danger: &DangerMap) -> Option<Action> {
+        // Bots read the game state directly, diffs are useless
+        self.rx.lock().unwrap().clear();
+        let me = &game.map.players[self.gid as usize];
+        if me.dead {
+            return None;
+        }
+        let pos = (me.x as usize, me.y as usize);
+        if game.tick() >= self.next_decision {
+            let reaction = self.difficulty.reaction_time() * game.tick_rate() as u64 / 1000;
+            self.next_decision = game.tick() + reaction.max(1);
+            if let Some(action) = self.decide(game, pos, danger) {
+                return Some(action);
+            }
+        }
+        while self.path.front() == Some(&pos) {
+            self.path.pop_front();
+        }
+        let next = *self.path.front()?;
+        let direction = neighbours(&game.map, pos).into_iter()
+            .find(|(square, _)| *square == next)
+            .map(|(_, direction)| direction);
+        if direction.is_none() || !walkable(&game.map, next) {
+            self.path.clear();
+            return None;
+        }
+        if !danger.contains(&pos) && danger.contains(&next) {
+            // Wait for the end of the explosion
+            return None;
+        }
+        Some(Action::Move(direction.unwrap()))
+    }
+
+    fn decide(&mut self, game: &Game, pos: (usize, usize), danger: &DangerMap) -> Option<Action> {
+        let map = &game.map;
+        if danger.contains(&pos) {
+            // Flee to the nearest safe square
+            self.path = bfs(map, pos, &HashSet::new(), usize::MAX, |p| !danger.contains(&p))
+                .unwrap_or_default();
+            return None;
+        }
+        if self.rng.gen::<f32>() < self.difficulty.mistakes() {
+            let moves: Vec<(usize, usize)> = neighbours(map, pos).into_iter()
+                .map(|(square, _)| square)
+                .filter(|square| walkable(map, *square) && !danger.contains(square))
+                .collect();
+            if !moves.is_empty() {
+                self.path = vec![moves[self.rng.gen_range(0, moves.len())]].into();
+            }
+            return None;
+        }
+        let can_bomb = self.can_bomb(game, pos);
+        if can_bomb && self.should_bomb(game, pos, danger) {
+            if let Some(escape) = self.escape_after_bomb(game, pos, danger) {
+                self.path = escape;
+                return Some(Action::PutBomb);
+            }
+        }
+        // Collect bonuses, or go near boxes to break them
+        let hunt = self.difficulty == Difficulty::Hard;
+        let path = bfs(map, pos, danger, usize::MAX, |p| {
+            if let Some(item) = &map.items[p.0 + p.1 * map.w] {
+                if item.name() == "Bonus" {
+                    return true;
+                }
+            }
+            if !can_bomb || p == pos {
+                return false;
+            }
+            neighbours(map, p).iter().any(|(n, _)| {
+                let box_near = match &map.items[n.0 + n.1 * map.w] {
+                    Some(item) => item.name() == "DestructibleBox",
+                    None => false,
+                };
+                box_near || (hunt && self.opponent_at(game, *n))
+            }) || (hunt && self.opponent_at(game, p))
+        });
+        if let Some(path) = path {
+            self.path = path;
+            return None;
+        }
+        if self.difficulty == Difficulty::Easy {
+            return None;
+        }
+        // Nothing left to break, hunt opponents
+        self.path = bfs(map, pos, danger, usize::MAX, |p| {
+            p != pos && neighbours(map, p).iter().any(|(n, _)| self.opponent_at(game, *n))
+        }).unwrap_or_default();
+        None
+    }
+
+    /**
+     * @return if an opponent of the bot is alive on a square
+     */
+    fn opponent_at(&self, game: &Game, pos: (usize, usize)) -> bool {
+        let me = &game.map.players[self.gid as usize];
+        game.map.players.iter().enumerate().any(|(gid, p)| {
+            gid as u64 != self.gid && !p.dead
+                && (me.team.is_none() || me.team != p.team)
+                && (p.x as usize, p.y as usize) == pos
+        })
+    }
+
+    fn can_bomb(&self, game: &Game, pos: (usize, usize)) -> bool {
+        let me = &game.map.players[self.gid as usize];
+        let current_bombs = game.bombs.iter()
+            .filter(|b| b.creator_id as u64 == self.gid)
+            .count() as u32;
+        current_bombs < me.bomb && game.map.items[pos.0 + pos.1 * game.map.w].is_none()
+    }
+
+    fn my_blast(&self, game: &Game, pos: (usize, usize)) -> DangerMap {
+        let me = &game.map.players[self.gid as usize];
+        blast(&game.map, pos, me.radius as i32, game.bomb_shape(self.gid))
+    }
+
+    fn should_bomb(&self, game: &Game, pos: (usize, usize), danger: &DangerMap) -> bool {
+        let map = &game.map;
+        let reached = self.my_blast(game, pos);
+        let box_reached = neighbours(map, pos).iter().any(|(n, _)| {
+            match &map.items[n.0 + n.1 * map.w] {
+                Some(item) => item.name() == "DestructibleBox",
+                None => false,
+            }
+        });
+        if box_reached {
+            return true;
+        }
+        if self.difficulty == Difficulty::Easy {
+            return false;
+        }
+        let mut new_danger = danger.clone();
+        new_danger.extend(reached.iter().cloned());
+        reached.iter().any(|p| {
+            if !self.opponent_at(game, *p) {
+                return false;
+            }
+            if self.difficulty == Difficulty::Normal {
+                return true;
+            }
+            // Only waste bombs on trapped opponents
+            bfs(map, *p, &HashSet::new(), TRAP_ESCAPE, |e| !new_danger.contains(&e)).is_none()
+        })
+    }
+
+    /**
+     * @return the path to a safe square if the bot put a bomb here
+     */
+    fn escape_after_bomb(&self, game: &Game, pos: (usize, usize), danger: &DangerMap)
+        -> Option<VecDeque<(usize, usize)>>
+    {
+        let mut new_danger = danger.clone();
+        new_danger.extend(self.my_blast(game, pos));
+        bfs(&game.map, pos, danger, MAX_ESCAPE, |p| !new_danger.contains(&p))
+    }
+}
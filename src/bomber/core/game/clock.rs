@@ -0,0 +1,92 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use std::time::Duration;
+
+/**
+ * Source of time for a Game. The simulation never reads the wall clock,
+ * so a match can be replayed identically from the same inputs.
+ */
+pub trait GameClock: Send {
+    /**
+     * @return the number of ticks simulated since the creation of the game
+     */
+    fn tick(&self) -> u64;
+
+    /**
+     * @return the number of ticks simulated per second
+     */
+    fn tick_rate(&self) -> u32;
+
+    /**
+     * Advance the clock by one tick
+     */
+    fn advance(&mut self);
+
+    /**
+     * @return the game time elapsed since the creation of the game
+     */
+    fn now(&self) -> Duration {
+        Duration::from_secs(self.tick()) / self.tick_rate()
+    }
+}
+
+/**
+ * Clock advanced by the fixed timestep simulation
+ */
+pub struct TickClock {
+    tick: u64,
+    tick_rate: u32,
+}
+
+impl TickClock {
+    /**
+     * Create a new TickClock
+     * @param tick_rate     Ticks per second
+     * @return              The created TickClock
+     */
+    pub fn new(tick_rate: u32) -> TickClock {
+        TickClock {
+            tick: 0,
+            tick_rate: tick_rate.max(1),
+        }
+    }
+}
+
+impl GameClock for TickClock {
+    fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    fn advance(&mut self) {
+        self.tick += 1;
+    }
+}
\ No newline at end of file
@@ -25,17 +25,30 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
-use rand::Rng;
-use std::collections::{ HashMap, HashSet, VecDeque };
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::collections::{ BTreeSet, HashMap, HashSet, VecDeque };
 use std::time::{Duration, Instant};
-use std::f64::consts::PI;
 
+use super::clock::{GameClock, TickClock};
+use super::replay::{Replay, ReplayAction};
+use super::settings::GameSettings;
+use super::stats::PlayerStats;
 use crate::bomber::core::Player;
 use crate::bomber::gen::{Map, item::*, utils::*};
+use crate::bomber::gen::generator::{generator_by_name, ClassicGenerator};
 use crate::bomber::net::diff_msg::*;
+use crate::bomber::net::msg::ServerMessage;
 
 // TODO redo this file
 
+pub const DEFAULT_TICK_RATE: u32 = 60;
+// Number of squares a punched bomb flies over
+const PUNCH_DISTANCE: i32 = 3;
+// Repelled bombs move at 2 squares/seconds, in steps of 50ms
+const BOMB_SPEED: f32 = 2.0;
+const BOMB_MOVE_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Clone)]
 pub struct GamePlayer {
     id: i32,
@@ -46,9 +59,10 @@ pub struct GamePlayer {
 #[derive(Clone)]
 pub struct ExplodingInfo {
     radius: i32,
-    exploding_time: Instant,
-    blocked_pos: HashSet<(i32, i32)>,
-    exploding_pos: HashSet<(i32, i32)>
+    exploding_time: Duration,
+    // Ordered, so blasts roll drops and kill players in the same order for a given seed
+    blocked_pos: BTreeSet<(i32, i32)>,
+    exploding_pos: BTreeSet<(i32, i32)>
 }
 
 #[derive(Clone)]
@@ -56,7 +70,7 @@ pub struct Bomb {
     pub creator_id: u32,
     pub radius: usize,
     pub shape: Shape,
-    pub created_time: Instant,
+    pub created_time: Duration,
     pub duration: Duration,
     pub pos: (f32, f32),
     pub exploding_info: Option<ExplodingInfo>,
@@ -67,68 +81,184 @@ pub struct Game {
     pub map: Map,
     pub players: Vec<GamePlayer>,
     pub bombs: Vec<Bomb>,
+    pub stats: Vec<PlayerStats>,
     pub game_player_to_player: HashMap<u64, Player>,
-    started: Instant,
+    started: Duration,
     duration: Duration,
     players_len: u32,
+    clock: Box<dyn GameClock>,
+    seed: u64,
+    rng: StdRng,
+    replay: Replay,
+    settings: GameSettings,
+    over: bool,
     last_printed: Instant,
-    last_update_bomb: Instant,
+    last_update_bomb: u64,
+    filled_squares: usize,  /* squares already filled by the sudden death */
     fps_instants: VecDeque<Instant>
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Action {
     PutBomb,
     Move(Direction),
+    Punch(Direction),
+    Leave,
 }
 
 impl Game {
     pub fn new() -> Game {
-        let map = Map::new(13, 11);
+        Game::new_with_settings(GameSettings::default())
+    }
+
+    /**
+     * Create a game with custom settings
+     * @param settings  The settings (assumed valid)
+     * @return          The created Game
+     */
+    pub fn new_with_settings(settings: GameSettings) -> Game {
+        Game::new_with_clock(Box::new(TickClock::new(DEFAULT_TICK_RATE)), rand::random(), settings)
+    }
+
+    /**
+     * Create a deterministic game. The same clock, seed and settings with the same
+     * actions will always produce the same game
+     * @param clock     The clock driving the game
+     * @param seed      Seed of the random generator used by the map and items
+     * @param settings  The settings (assumed valid)
+     * @return          The created Game
+     */
+    pub fn new_with_clock(clock: Box<dyn GameClock>, seed: u64, settings: GameSettings) -> Game {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (width, height) = settings.map_size();
+        let generator = generator_by_name(&settings.generator).unwrap_or_else(|| Box::new(ClassicGenerator));
+        let map = Map::generate(&*generator, width, height, settings.players as usize, &mut rng);
+        let mut game = Game::new_with_map(clock, seed, settings, map);
+        // Generated maps are reproduced from the seed
+        game.replay.map = None;
+        game
+    }
+
+    /**
+     * Create a game on an existing map, with one player per spawn of the map
+     * @param clock     The clock driving the game
+     * @param seed      Seed of the random generator
+     * @param settings  The settings (assumed valid)
+     * @param map       The map to play
+     * @return          The created Game
+     */
+    pub fn new_with_map(clock: Box<dyn GameClock>, seed: u64, settings: GameSettings, mut map: Map) -> Game {
+        let rng = StdRng::seed_from_u64(seed);
+        let mut replay = Replay::new(seed, clock.tick_rate(), settings.clone());
+        replay.map = Some(map.clone());
+        for player in &mut map.players {
+            player.bomb = settings.bombs;
+            player.radius = settings.radius;
+            player.speed_factor = settings.speed_factor;
+        }
+        let players_len = map.players.len() as u32;
         let mut players = Vec::new();
-        for id in 0..4 {
+        let mut stats = Vec::new();
+        for id in 0..players_len as i32 {
             players.push(GamePlayer {
                 id,
                 actions: Vec::new(),
                 effects: Vec::new()
             });
+            stats.push(PlayerStats::new(id as u64));
         }
         Game {
             map,
             players,
-            players_len: 4,
+            players_len,
             bombs: Vec::new(),
+            stats,
             game_player_to_player: HashMap::new(),
-            started: Instant::now(),
-            duration: Duration::from_secs(60 * 3),
+            started: clock.now(),
+            duration: settings.duration(),
+            clock,
+            seed,
+            rng,
+            replay,
+            settings,
+            over: false,
             last_printed: Instant::now(),
-            last_update_bomb: Instant::now(),
+            last_update_bomb: 0,
+            filled_squares: 0,
             fps_instants: VecDeque::new(),
         }
     }
 
     pub fn start(&mut self) {
-        self.started = Instant::now();
+        self.started = self.clock.now();
         self.last_printed = Instant::now();
-        self.last_update_bomb = Instant::now();
+        self.last_update_bomb = self.clock.tick();
+    }
+
+    /**
+     * @return the number of ticks simulated since the creation of the game
+     */
+    pub fn tick(&self) -> u64 {
+        self.clock.tick()
+    }
+
+    /**
+     * @return the number of ticks simulated per second
+     */
+    pub fn tick_rate(&self) -> u32 {
+        self.clock.tick_rate()
+    }
+
+    /**
+     * @return the seed used to generate the game
+     */
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /**
+     * Convert a duration into a number of ticks (at least one)
+     * @param duration  The duration to convert
+     * @return          The number of ticks
+     */
+    fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        let ticks = (duration.as_secs_f64() * self.tick_rate() as f64).round() as u64;
+        ticks.max(1)
     }
 
 
     pub fn push_action(&mut self, action: Action, player_id: u64) {
+        self.replay.actions.push(ReplayAction {
+            tick: self.clock.tick(),
+            player: player_id,
+            action: action.clone(),
+        });
         self.players[player_id as usize].actions.push(action);
     }
 
+    /**
+     * @return the replay of the game so far
+     */
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
     pub fn link_player(&mut self, player: Player) -> Option<u64> {
         if self.game_player_to_player.len() == self.players.len() {
             warn!("Can't link player because room is full");
             return None;
         }
         let id = self.game_player_to_player.len() as u64;
-        let diff = PlayerIdentity {
-            msg_type: String::from("player_identity"),
+        self.map.players[id as usize].team = player.team;
+        let diff = ServerMessage::PlayerIdentity(PlayerIdentity {
             id,
-        };
+            name: player.name.clone(),
+            team: player.team,
+        });
+        self.stats[id as usize].name = player.name.clone();
         player.rx.lock().unwrap().push(diff.to_vec());
+        self.replay.names.push(player.name.clone());
+        self.replay.teams.push(player.team);
         self.game_player_to_player.insert(id, player);
 
         Some(id)
@@ -140,17 +270,111 @@ impl Game {
         }
     }
 
+    /**
+     * A side is a team in team mode, else the player itself
+     * @param gid   The player id
+     * @return      The side of the player
+     */
+    fn side(&self, gid: u64) -> u64 {
+        match self.map.players[gid as usize].team {
+            Some(team) => team as u64,
+            None => u64::max_value() - gid,
+        }
+    }
+
+    /**
+     * @return the number of sides among linked players
+     */
+    fn linked_sides(&self) -> usize {
+        let sides: HashSet<u64> = (0..self.game_player_to_player.len() as u64)
+            .map(|gid| self.side(gid)).collect();
+        sides.len()
+    }
+
+    /**
+     * @return the linked players still alive
+     */
+    fn alive_players(&self) -> Vec<u64> {
+        let linked = self.game_player_to_player.len();
+        self.map.players.iter().enumerate()
+            .filter(|(idx, p)| *idx < linked && !p.dead)
+            .map(|(idx, _)| idx as u64)
+            .collect()
+    }
+
+    /**
+     * A game is finished when only one side (or nobody) is still alive.
+     * A game with only one side is finished when everybody is dead.
+     */
     pub fn finished(&self) -> bool {
-        let mut deads = 0;
-        let mut idx = 0;
-        for p in &self.map.players {
-            if p.dead
-            && idx < self.game_player_to_player.len() /* linked */ {
-                deads += 1;
+        let alive_sides: HashSet<u64> = self.alive_players().iter()
+            .map(|gid| self.side(*gid)).collect();
+        if self.linked_sides() > 1 {
+            alive_sides.len() <= 1
+        } else {
+            alive_sides.is_empty()
+        }
+    }
+
+    /**
+     * @return the last player alive, None if the game is running, is a draw or is played by teams
+     */
+    pub fn winner(&self) -> Option<u64> {
+        if !self.finished() || self.linked_sides() < 2 {
+            return None;
+        }
+        let alive = self.alive_players();
+        if alive.len() != 1 || self.map.players[alive[0] as usize].team.is_some() {
+            return None;
+        }
+        Some(alive[0])
+    }
+
+    /**
+     * @return the last team alive, None if the game is running, is a draw or is free for all
+     */
+    pub fn winning_team(&self) -> Option<u32> {
+        if !self.finished() || self.linked_sides() < 2 {
+            return None;
+        }
+        self.alive_players().first().and_then(|gid| self.map.players[*gid as usize].team)
+    }
+
+    /**
+     * @return statistics of the linked players
+     */
+    pub fn scoreboard(&self) -> Vec<PlayerStats> {
+        self.stats.iter().take(self.game_player_to_player.len()).cloned().collect()
+    }
+
+    fn game_over(&mut self) {
+        self.over = true;
+        let winner = self.winner();
+        let winning_team = self.winning_team();
+        info!("Game over, winner: {:?}, winning team: {:?}", winner, winning_team);
+        let diff = ServerMessage::GameOver(GameOver {
+            winner,
+            winning_team,
+            draw: winner.is_none() && winning_team.is_none(),
+            scoreboard: self.scoreboard(),
+        });
+        self.inform_players(&diff.to_vec());
+    }
+
+    /**
+     * @param gid   The player id
+     * @return      The shape of the next bombs of a player
+     */
+    pub fn bomb_shape(&self, gid: u64) -> Shape {
+        let mut shape = Shape::Cross;
+        for effect in &self.players[gid as usize].effects {
+            if effect.bonus == Some(Bonus::SquareBombs) {
+                shape = Shape::Square;
+            } else if effect.bonus == Some(Bonus::CircleBombs) {
+                shape = Shape::Circle;
             }
-            idx += 1;
         }
-        deads >= self.game_player_to_player.len()
+        shape
     }
 
     fn execute(&mut self, action: Action, player_id: i32) {
@@ -174,31 +398,44 @@ impl Game {
                     info!("Player {} already launch all the bomb", player_id);
                     return;
                 }
-                let mut bomb_duration = Duration::from_secs(3);
+                let mut bomb_duration = self.settings.bomb_fuse();
                 for effect in &self.players[player_id as usize].effects {
                     if effect.malus.is_some() {
                         if effect.malus == Some(Malus::SpeedBomb) {
-                            bomb_duration = Duration::from_secs_f32(1.8);
+                            bomb_duration = bomb_duration.mul_f32(0.6);
                         }
                     }
                 }
+                let shape = self.bomb_shape(player_id as u64);
                 self.map.items[player.x as usize + player.y as usize * self.map.w] = Some(Box::new(BombItem {}));
+                self.stats[player_id as usize].bombs += 1;
                 self.bombs.push(Bomb {
                     creator_id: player_id as u32,
                     radius: player.radius as usize,
-                    shape: Shape::Cross,
-                    created_time: Instant::now(),
+                    shape,
+                    created_time: self.clock.now(),
                     duration: bomb_duration,
                     pos: (player.x as usize as f32 + 0.5, player.y as usize as f32 + 0.5),
                     exploding_info: None,
                     moving_dir: None,
                 });
-                let diff = PlayerPutBomb {
-                    msg_type: String::from("player_put_bomb_diff"),
+                let diff = ServerMessage::PlayerPutBomb(PlayerPutBomb {
                     id: player_id,
                     x: player.x as usize,
                     y: player.y as usize,
-                };
+                    shape,
+                });
+                self.inform_players(&diff.to_vec());
+            },
+            Action::Leave => {
+                // The character of a player who left the game dies
+                self.map.players[player_id as usize].dead = true;
+                self.stats[player_id as usize].deaths += 1;
+                let diff = ServerMessage::PlayerDie(PlayerDie {
+                    id: player_id as u64,
+                    killer: None,
+                    suicide: false,
+                });
                 self.inform_players(&diff.to_vec());
             },
             Action::Move(direction) => {
@@ -255,16 +492,96 @@ impl Game {
                 if walkable {
                     player.x = x;
                     player.y = y;
-                    let diff = PlayerMove {
-                        msg_type: String::from("player_move_diff"),
+                    let diff = ServerMessage::PlayerMove(PlayerMove {
                         id: player_id,
                         x,
                         y,
-                    };
+                    });
                     self.inform_players(&diff.to_vec());
                 }
             }
+            Action::Punch(direction) => {
+                let can_punch = self.players[player_id as usize].effects.iter()
+                    .any(|e| e.bonus == Some(Bonus::PunchBombs));
+                if !can_punch {
+                    info!("Player {} can't punch bombs", player_id);
+                    return;
+                }
+                self.punch_bomb(player_id, direction);
+            }
+        }
+    }
+
+    /**
+     * Throw the bomb next to a player over walls and boxes. The bomb wraps
+     * around the edges of the map and bounces on squares where it can't land
+     * @param player_id     The player punching
+     * @param direction     The direction of the punch
+     */
+    fn punch_bomb(&mut self, player_id: i32, direction: Direction) {
+        let player = &self.map.players[player_id as usize];
+        let (dx, dy) = match direction {
+            Direction::North => (0, -1),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+            Direction::East => (1, 0),
+        };
+        let x = player.x as i32 + dx;
+        let y = player.y as i32 + dy;
+        if x < 0 || x >= self.map.w as i32 || y < 0 || y >= self.map.h as i32 {
+            return;
+        }
+        let bomb_idx = self.bombs.iter().position(|b| {
+            b.exploding_info.is_none() && b.pos.0 as i32 == x && b.pos.1 as i32 == y
+        });
+        let bomb_idx = match bomb_idx {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let mut distance = PUNCH_DISTANCE;
+        let mut landing = None;
+        for _ in 0..(self.map.w * self.map.h) {
+            let nx = (x + dx * distance).rem_euclid(self.map.w as i32);
+            let ny = (y + dy * distance).rem_euclid(self.map.h as i32);
+            if nx == x && ny == y {
+                break; // The bomb made a full turn
+            }
+            if self.can_land(nx as usize, ny as usize) {
+                landing = Some((nx as usize, ny as usize));
+                break;
+            }
+            distance += 1;
         }
+        let (nx, ny) = match landing {
+            Some(pos) => pos,
+            None => return,
+        };
+
+        self.map.items[x as usize + y as usize * self.map.w] = None;
+        self.map.items[nx + ny * self.map.w] = Some(Box::new(BombItem {}));
+        let bomb = &mut self.bombs[bomb_idx];
+        let old_pos = bomb.pos;
+        bomb.pos = (nx as f32 + 0.5, ny as f32 + 0.5);
+        bomb.moving_dir = None;
+        let diff = ServerMessage::BombPunched(BombPunched {
+            old_x: old_pos.0,
+            old_y: old_pos.1,
+            x: bomb.pos.0,
+            y: bomb.pos.1,
+        });
+        self.inform_players(&diff.to_vec());
+    }
+
+    /**
+     * @return if a thrown bomb can land on a square
+     */
+    fn can_land(&self, x: usize, y: usize) -> bool {
+        if self.map.squares[x + y * self.map.w].sq_type != SquareType::Empty
+        || self.map.items[x + y * self.map.w].is_some() {
+            return false;
+        }
+        !self.map.players.iter().any(|p| !p.dead && p.x as usize == x && p.y as usize == y)
     }
 
     fn execute_actions(&mut self) {
@@ -305,6 +622,8 @@ impl Game {
     }
 
     fn eat_bonus_and_malus(&mut self) {
+        let now = self.clock.now();
+        let malus_duration = self.settings.malus_duration();
         let mut pkts: Vec<Vec<u8>> = Vec::new();
         let mut idx = 0;
         for p in &mut self.map.players {
@@ -326,13 +645,12 @@ impl Game {
                         p.radius += 1;
                     },
                     Bonus::PunchBombs => {
-                        info!("Player {} can push bombs", idx);
+                        info!("Player {} can punch bombs", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
                             end: None,
                             malus: None,
                             bonus: Some(Bonus::PunchBombs)
                         });
-                        error!("TODO");
                     },
                     Bonus::ImproveSpeed => {
                         info!("Improve player {} speed", idx);
@@ -350,6 +668,18 @@ impl Game {
                         info!("Player {} have more bombs", idx);
                         p.bomb += 1;
                     },
+                    Bonus::SquareBombs | Bonus::CircleBombs => {
+                        info!("Player {} bombs have a new shape", idx);
+                        // Only the last shape eaten is kept
+                        self.players[idx as usize].effects.retain(|e| {
+                            e.bonus != Some(Bonus::SquareBombs) && e.bonus != Some(Bonus::CircleBombs)
+                        });
+                        self.players[idx as usize].effects.push(PlayerEffect {
+                            end: None,
+                            malus: None,
+                            bonus: Some(bonus.unwrap().clone())
+                        });
+                    },
                     _ => {
                         error!("Unknown bonus");
                     },
@@ -363,7 +693,7 @@ impl Game {
                     Malus::Slow => {
                         info!("Player {} is now slow", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
-                            end: Some(Instant::now() + Duration::from_secs(10)),
+                            end: Some(now + malus_duration),
                             malus: Some(Malus::Slow),
                             bonus: None,
                         });
@@ -371,7 +701,7 @@ impl Game {
                     Malus::UltraFast => {
                         info!("Player {} gotta go fast", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
-                            end: Some(Instant::now() + Duration::from_secs(10)),
+                            end: Some(now + malus_duration),
                             malus: Some(Malus::UltraFast),
                             bonus: None,
                         });
@@ -379,7 +709,7 @@ impl Game {
                     Malus::SpeedBomb => {
                         info!("Change player {} bomb' speed", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
-                            end: Some(Instant::now() + Duration::from_secs(10)),
+                            end: Some(now + malus_duration),
                             malus: Some(Malus::SpeedBomb),
                             bonus: None,
                         });
@@ -387,7 +717,7 @@ impl Game {
                     Malus::DropBombs => {
                         info!("Player {} drop bombs as fast as they can", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
-                            end: Some(Instant::now() + Duration::from_secs(10)),
+                            end: Some(now + malus_duration),
                             malus: Some(Malus::DropBombs),
                             bonus: None,
                         });
@@ -395,7 +725,7 @@ impl Game {
                     Malus::InvertedControls => {
                         info!("Player {} have inverted controls", idx);
                         self.players[idx as usize].effects.push(PlayerEffect {
-                            end: Some(Instant::now() + Duration::from_secs(10)),
+                            end: Some(now + malus_duration),
                             malus: Some(Malus::InvertedControls),
                             bonus: None,
                         });
@@ -407,12 +737,12 @@ impl Game {
             }
 
             if inform {
+                self.stats[idx as usize].items += 1;
                 self.map.items[p.x as usize + self.map.w * p.y as usize] = None;
-                let diff = DestroyItem {
-                    msg_type: String::from("destroy_item"),
+                let diff = ServerMessage::DestroyItem(DestroyItem {
                     w: p.x as u64,
                     h: p.y as u64,
-                };
+                });
                 pkts.push(diff.to_vec());
             }
             idx += 1;
@@ -426,7 +756,7 @@ impl Game {
         for p in &mut self.players {
             let mut idx = 0;
             for effect in p.effects.clone() {
-                if effect.end.is_some() && effect.end.unwrap() < Instant::now() {
+                if effect.end.is_some() && effect.end.unwrap() < now {
                     p.effects.remove(idx as usize);
                 } else {
                     idx += 1;
@@ -435,118 +765,121 @@ impl Game {
         }
     }
 
-    fn update_exploding_radius(&mut self, bomb_idx: usize) -> Option<i32> {
-        let mut bomb = &mut self.bombs[bomb_idx];
-
-        // Explode
-        let mut exploding_radius = 0;
-        if bomb.exploding_info.is_none() {
-            bomb.exploding_info = Some(ExplodingInfo {
-                radius: 0,
-                exploding_time: Instant::now(),
-                blocked_pos: HashSet::new(),
-                exploding_pos: HashSet::new(),
-            });
-            let diff = BombExplode {
-                msg_type: String::from("bomb_explode"),
-                w: bomb.pos.0 as u64,
-                h: bomb.pos.1 as u64,
-            };
-            self.inform_players(&diff.to_vec());
-        } else {
-            exploding_radius = bomb.exploding_info.as_ref().unwrap().radius;
-            if exploding_radius == bomb.radius as i32 {
-                // End of the bomb
-                self.map.items[bomb.pos.0 as usize + self.map.w * bomb.pos.1 as usize] = None;
-                self.bombs.remove(bomb_idx);
-                return None;
-            }
+    fn start_explosion(&mut self, bomb_idx: usize) {
+        let bomb = &mut self.bombs[bomb_idx];
+        bomb.exploding_info = Some(ExplodingInfo {
+            radius: 0,
+            exploding_time: self.clock.now(),
+            blocked_pos: BTreeSet::new(),
+            exploding_pos: BTreeSet::new(),
+        });
+        let diff = ServerMessage::BombExplode(BombExplode {
+            w: bomb.pos.0 as u64,
+            h: bomb.pos.1 as u64,
+        });
+        self.inform_players(&diff.to_vec());
+    }
 
-            let exploding_time = bomb.exploding_info.as_ref().unwrap().exploding_time;
-            if Instant::now() - Duration::from_millis((exploding_radius as u64 + 1) * 100) >= exploding_time {
-                exploding_radius += 1;
-                match bomb.exploding_info {
-                    Some(ref mut info) => {
-                        info.radius = exploding_radius;
-                    }
-                    _ => {}
-                };
-            }
+    /**
+     * Make the explosion of a bomb grow
+     * @param bomb_idx  The exploding bomb
+     * @return          The current radius, None if the explosion is finished
+     */
+    fn update_exploding_radius(&mut self, bomb_idx: usize) -> Option<i32> {
+        if self.bombs[bomb_idx].exploding_info.is_none() {
+            self.start_explosion(bomb_idx);
+            return Some(0);
+        }
+        let now = self.clock.now();
+        let bomb = &mut self.bombs[bomb_idx];
+        let info = bomb.exploding_info.as_mut().unwrap();
+        if info.radius == bomb.radius as i32 {
+            // End of the bomb
+            return None;
+        }
+        if now >= info.exploding_time + Duration::from_millis((info.radius as u64 + 1) * 100) {
+            info.radius += 1;
         }
-        Some(exploding_radius)
+        Some(info.radius)
     }
 
     fn update_exploding_pos(&mut self, bomb_idx: usize) {
         let bomb = &mut self.bombs[bomb_idx];
         let radius = bomb.exploding_info.as_ref().unwrap().radius;
+        let bomb_pos = (bomb.pos.0 as usize, bomb.pos.1 as usize);
         for r in 0..(radius + 1) {
-            // TODO other than Cross
-            for angle in (0..360).step_by(90) {
-                let angle = (angle as f64 / 360.) * (2. * PI);
-                let x = bomb.pos.0 as i32 + (angle.cos() as i32 * r);
-                let y = bomb.pos.1 as i32 + (angle.sin() as i32 * r);
+            for offset in bomb.shape.ring(r) {
+                let x = bomb_pos.0 as i32 + offset.0;
+                let y = bomb_pos.1 as i32 + offset.1;
                 if x < 0 || x >= self.map.w as i32 || y < 0 || y >= self.map.h as i32 {
                     continue;
                 }
-                let pos = (x, y);
-                if bomb.exploding_info.as_ref().unwrap().blocked_pos.contains(&pos) {
+                let info = bomb.exploding_info.as_mut().unwrap();
+                if info.blocked_pos.contains(&(x, y)) {
                     continue; // Already checked
                 }
-                let pos = (x as usize, y as usize);
                 // Check if path to the bomb is blocked
-                let mut blocked = false;
-                for pr in 0..r {
-                    let px = bomb.pos.0 as i32 + (angle.cos() as i32 * pr);
-                    let py = bomb.pos.1 as i32 + (angle.sin() as i32 * pr);
-                    if bomb.exploding_info.as_ref().unwrap().blocked_pos.contains(&(px, py)) {
-                        blocked = true;
-                        break;
-                    }
-                }
+                let blocked = Shape::path(&offset).iter().any(|p| {
+                    info.blocked_pos.contains(&(bomb_pos.0 as i32 + p.0, bomb_pos.1 as i32 + p.1))
+                });
                 if blocked {
                     continue;
                 }
-                let (block, clear) = self.map.squares[pos.0 + self.map.w * pos.1]
-                    .sq_type.explode_event(&(pos.0, pos.1), &(bomb.pos.0 as usize, bomb.pos.1 as usize));
-                blocked = block;
+                let pos = (x as usize, y as usize);
+                let (mut blocked, clear) = self.map.squares[pos.0 + self.map.w * pos.1]
+                    .sq_type.explode_event(&pos, &bomb_pos);
                 if !blocked {
-                    let item = &self.map.items[pos.0 + self.map.w * pos.1];
-                    if item.is_some() {
+                    if let Some(item) = &self.map.items[pos.0 + self.map.w * pos.1] {
                         // TODO as_ref for moving blocked_pos
-                        let (iblock, _) = item.as_ref().unwrap()
-                            .explode_event(&pos, &(bomb.pos.0 as usize, bomb.pos.1 as usize));
+                        let (iblock, _) = item.explode_event(&pos, &bomb_pos);
                         blocked = iblock;
                     }
                 }
                 // Check if clear square
                 if clear {
-                    bomb.exploding_info.as_mut().unwrap().exploding_pos.insert((pos.0 as i32, pos.1 as i32));
+                    info.exploding_pos.insert((x, y));
                 }
-                // Check if bomb is stopped
-                if blocked && clear {
-                    let nxt_x = bomb.pos.0 as i32 + (angle.cos() as i32 * (r + 1));
-                    let nxt_y = bomb.pos.1 as i32 + (angle.sin() as i32 * (r + 1));
-                    bomb.exploding_info.as_mut().unwrap().blocked_pos.insert((nxt_x, nxt_y));
-                } else if blocked {
-                    bomb.exploding_info.as_mut().unwrap().blocked_pos.insert((pos.0 as i32, pos.1 as i32));
+                // Squares behind a blocked square are not reached
+                if blocked {
+                    info.blocked_pos.insert((x, y));
                 }
             }
         }
     }
 
-    fn kill_players(&mut self, x: i32, y: i32) {
+    /**
+     * Kill players on a square
+     * @param x         The square's x
+     * @param y         The square's y
+     * @param killer    The creator of the bomb, None if killed by the map
+     */
+    fn kill_players(&mut self, x: i32, y: i32, killer: Option<u32>) {
         let mut pkts = Vec::new();
         let mut p = 0;
+        let killer_team = killer.and_then(|k| self.map.players[k as usize].team);
         for player in &mut self.map.players {
             if !player.dead
             && player.x as usize == x as usize
             && player.y as usize == y as usize {
-                let diff = PlayerDie {
-                    msg_type: String::from("player_die"),
+                let suicide = killer == Some(p as u32);
+                if !self.settings.friendly_fire && !suicide
+                && killer_team.is_some() && killer_team == player.team {
+                    p += 1;
+                    continue;
+                }
+                let diff = ServerMessage::PlayerDie(PlayerDie {
                     id: p as u64,
-                };
+                    killer: killer.map(|k| k as u64),
+                    suicide,
+                });
                 pkts.push(diff.to_vec());
                 player.dead = true;
+                self.stats[p].deaths += 1;
+                if suicide {
+                    self.stats[p].suicides += 1;
+                } else if let Some(killer) = killer {
+                    self.stats[killer as usize].kills += 1;
+                }
             }
             p += 1;
         }
@@ -558,65 +891,70 @@ impl Game {
     fn remove_item(&mut self, x: i32, y: i32) {
         self.map.items[x as usize + self.map.w * y as usize] = None;
 
-        let diff = DestroyItem {
-            msg_type: String::from("destroy_item"),
+        let diff = ServerMessage::DestroyItem(DestroyItem {
             w: x as u64,
             h: y as u64,
-        };
+        });
         self.inform_players(&diff.to_vec());
     }
 
     fn bomb_events(&mut self) {
-        let mut explodings_bombs_idx = Vec::new();
+        let now = self.clock.now();
+        let mut explodings_bombs_idx = VecDeque::new();
         for bomb_idx in 0..self.bombs.len() {
-            let bomb = &mut self.bombs[bomb_idx];
+            let bomb = &self.bombs[bomb_idx];
             // Check if exploding
-            if Instant::now() - bomb.duration < bomb.created_time {
+            if bomb.exploding_info.is_none() && now < bomb.created_time + bomb.duration {
                 continue;
             }
-            explodings_bombs_idx.push(bomb_idx);
+            explodings_bombs_idx.push_back(bomb_idx);
         }
+        let mut processed_bombs_idx = HashSet::new();
+        let mut finished_bombs_idx = Vec::new();
         let mut pkts = Vec::new();
-        for bomb_idx in explodings_bombs_idx {
+        while let Some(bomb_idx) = explodings_bombs_idx.pop_front() {
+            if !processed_bombs_idx.insert(bomb_idx) {
+                continue;
+            }
             // Explode bomb
             let exploding_radius = self.update_exploding_radius(bomb_idx);
             if exploding_radius.is_none() {
+                finished_bombs_idx.push(bomb_idx);
                 continue;
             }
             self.update_exploding_pos(bomb_idx);
 
             for (x, y) in self.bombs[bomb_idx].exploding_info.as_ref().unwrap().exploding_pos.clone() {
-                self.kill_players(x, y);
+                // Flames detonate other bombs immediately
+                for chained_idx in self.bombs_to_chain(x, y) {
+                    self.start_explosion(chained_idx);
+                    let diff = ServerMessage::BombChain(BombChain {
+                        from_w: self.bombs[bomb_idx].pos.0 as u64,
+                        from_h: self.bombs[bomb_idx].pos.1 as u64,
+                        w: x as u64,
+                        h: y as u64,
+                    });
+                    self.inform_players(&diff.to_vec());
+                    explodings_bombs_idx.push_back(chained_idx);
+                }
+                self.kill_players(x, y, Some(self.bombs[bomb_idx].creator_id));
                 // Destroy items in zone
                 let item = &self.map.items[x as usize + self.map.w * y as usize];
                 if item.is_some() {
                     let db = self.map.items[x as usize + self.map.w * y as usize].as_ref().unwrap().as_any().downcast_ref::<DestructibleBox>();
                     match db {
                         Some(_) => {
-                            let mut rng = rand::thread_rng();
-                            let prob = rng.gen_range(0, 5);
-                            if prob == 1 || prob == 2 {
-                                let bonus : Bonus = rand::random();
-                                self.map.items[x as usize + self.map.w * y as usize] = Some(bonus.box_clone());
-                                let diff = CreateItem {
-                                    msg_type: String::from("create_item"),
-                                    item: Some(Box::new(bonus)),
-                                    w: x as u64,
-                                    h: y as u64,
-                                };
-                                pkts.push(diff.to_vec());
-                            } else if prob == 3 {
-                                let malus : Malus = rand::random();
-                                self.map.items[x as usize + self.map.w * y as usize] = Some(malus.box_clone());
-                                let diff = CreateItem {
-                                    msg_type: String::from("create_item"),
-                                    item: Some(Box::new(malus)),
-                                    w: x as u64,
-                                    h: y as u64,
-                                };
-                                pkts.push(diff.to_vec());
-                            } else {
-                                self.remove_item(x, y);
+                            match self.settings.drops.roll(&mut self.rng) {
+                                Some(item) => {
+                                    self.map.items[x as usize + self.map.w * y as usize] = Some(item.clone());
+                                    let diff = ServerMessage::CreateItem(CreateItem {
+                                        item: Some(item),
+                                        w: x as u64,
+                                        h: y as u64,
+                                    });
+                                    pkts.push(diff.to_vec());
+                                },
+                                None => self.remove_item(x, y),
                             }
                         },
                         _ => {}
@@ -625,33 +963,65 @@ impl Game {
             }
         };
 
+        // Remove finished bombs, from the last one to keep indexes valid
+        finished_bombs_idx.sort_unstable();
+        for bomb_idx in finished_bombs_idx.into_iter().rev() {
+            let bomb = self.bombs.remove(bomb_idx);
+            self.map.items[bomb.pos.0 as usize + self.map.w * bomb.pos.1 as usize] = None;
+        }
+
         for pkt in pkts {
             self.inform_players(&pkt);
         }
     }
 
+    /**
+     * Get bombs waiting for their timer on a square
+     * @param x     The square's x
+     * @param y     The square's y
+     * @return      The index of the bombs
+     */
+    fn bombs_to_chain(&self, x: i32, y: i32) -> Vec<usize> {
+        let mut res = Vec::new();
+        for (idx, bomb) in self.bombs.iter().enumerate() {
+            if bomb.exploding_info.is_none()
+            && bomb.pos.0 as i32 == x && bomb.pos.1 as i32 == y {
+                res.push(idx);
+            }
+        }
+        res
+    }
+
+    /**
+     * Fill the map from its borders to its center during the sudden death. Every
+     * square is filled before the end of the game, even when there are more squares
+     * than ticks
+     */
     pub fn update_end_anim(&mut self) {
-        if self.started + self.duration - Duration::from_secs(30) <= Instant::now() {
-            let duration_left = self.started + self.duration - Instant::now();
-            let squares_nb = self.map.h * self.map.w;
-            let interval = Duration::from_secs(30).as_millis() / squares_nb as u128;
-            let square = (
-                (
-                    (Duration::from_secs(30).as_millis() - duration_left.as_millis()) as f32
-                    / Duration::from_secs(30).as_millis() as f32
-                ) * interval as f32
-            ) as u32;
-
-            // TODO more animations
-
-            let x = square as usize % self.map.w;
-            let y = square as usize / self.map.w;
+        // Squares reached before the next tick are filled now
+        let now = self.clock.now() + Duration::from_secs(1) / self.tick_rate();
+        let sudden_death = self.settings.sudden_death();
+        if sudden_death.as_millis() == 0 || self.started + self.duration > now + sudden_death {
+            return;
+        }
+        let duration_left = (self.started + self.duration).checked_sub(now).unwrap_or_default();
+        let squares_nb = self.map.h * self.map.w;
+        let elapsed = (sudden_death.as_millis() - duration_left.as_millis()) as f64 / sudden_death.as_millis() as f64;
+        let target = ((elapsed * squares_nb as f64) as usize).min(squares_nb);
+
+        // TODO more animations
+
+        while self.filled_squares < target {
+            let square = self.filled_squares;
+            self.filled_squares += 1;
+            let x = square % self.map.w;
+            let y = square / self.map.w;
             let linearized =
                 if y % 2 == 0 { (y / 2) * self.map.w + x }
                 else { squares_nb - 1 - ( y / 2 ) * self.map.w - x  };
 
             if self.map.squares[linearized].sq_type == SquareType::Block {
-                return;
+                continue;
             }
 
             let x = (linearized % self.map.w) as u64;
@@ -659,31 +1029,30 @@ impl Game {
 
             // The square is now a block
             self.map.squares[linearized].sq_type = SquareType::Block;
-            let diff = UpdateSquare {
-                msg_type: String::from("update_square"),
+            let diff = ServerMessage::UpdateSquare(UpdateSquare {
                 square: SquareType::Block,
                 x,
                 y,
-            };
+            });
             self.inform_players(&diff.to_vec());
             self.remove_item(x as i32, y as i32);
-            self.kill_players(x as i32, y as i32);
-
+            self.kill_players(x as i32, y as i32, None);
         }
     }
 
     fn update_bomb_position(&mut self) {
-        // The speed should be 2 squares/seconds
-        if self.last_update_bomb + Duration::from_millis(50) > Instant::now() {
+        let interval = self.duration_to_ticks(BOMB_MOVE_INTERVAL);
+        if self.last_update_bomb + interval > self.clock.tick() {
             return;
         }
-        let mut diffs = Vec::<BombMove>::new();
+        let bomb_increment = BOMB_SPEED * interval as f32 / self.tick_rate() as f32;
+        let mut diffs = Vec::<ServerMessage>::new();
         for b in &mut self.bombs {
             if b.moving_dir.is_none() || b.exploding_info.is_some() {
                 continue;
             }
             let direction = b.moving_dir.unwrap();
-            let increment = 0.1;
+            let increment = bomb_increment;
             let mut x = b.pos.0;
             let mut y = b.pos.1;
             match direction {
@@ -699,7 +1068,7 @@ impl Game {
             let mut walkable = x as usize == b.pos.0 as usize && y as usize == b.pos.1 as usize;
             if !walkable {
                 let fake_player = MapPlayer {
-                    x: b.pos.0, y: b.pos.1, radius: 0, speed_factor: 0, bomb: 0, dead: false,
+                    x: b.pos.0, y: b.pos.1, radius: 0, speed_factor: 0, bomb: 0, dead: false, team: None,
                 };
                 // Items should not be a bomb or a box
                 walkable = self.map.items[x as usize + y as usize * self.map.w].is_none()
@@ -726,13 +1095,12 @@ impl Game {
                     self.map.items[x as usize + y as usize * self.map.w] = Some(Box::new(BombItem {}));
                     self.map.items[b.pos.0 as usize + b.pos.1 as usize * self.map.w] = None;
                 }
-                diffs.push(BombMove {
-                    msg_type: String::from("bomb_move_diff"),
+                diffs.push(ServerMessage::BombMove(BombMove {
                     old_x: b.pos.0,
                     old_y: b.pos.1,
                     x,
                     y,
-                });
+                }));
                 b.pos = (x,y);
             } else {
                 b.moving_dir = None;
@@ -741,14 +1109,21 @@ impl Game {
         for diff in diffs {
             self.inform_players(&diff.to_vec());
         }
-        self.last_update_bomb = Instant::now();
+        self.last_update_bomb = self.clock.tick();
     }
 
+    /**
+     * Simulate one tick
+     */
     pub fn event_loop(&mut self) {
         self.execute_actions();
         self.eat_bonus_and_malus();
         self.bomb_events();
         self.update_end_anim();
         self.update_bomb_position();
+        if !self.over && self.finished() {
+            self.game_over();
+        }
+        self.clock.advance();
     }
 }
\ No newline at end of file
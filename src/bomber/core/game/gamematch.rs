@@ -0,0 +1,371 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use super::bot::{self, Bot};
+use super::clock::TickClock;
+use super::game::{Action, Game};
+use super::settings::GameSettings;
+use crate::bomber::core::Player;
+use crate::bomber::core::server::GameStream;
+use crate::bomber::gen::MapRotation;
+use crate::bomber::net::diff_msg::*;
+use crate::bomber::net::msg::{MapMsg, ServerMessage};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INTERMISSION_DURATION: Duration = Duration::from_secs(5);
+const COUNTDOWN_DURATION: Duration = Duration::from_secs(3);
+const MAPS_DIR: &str = "./maps";
+
+enum MatchState {
+    Countdown(u64 /* ticks left */),
+    Playing,
+    Intermission(u64 /* ticks left */),
+    Over,
+}
+
+/**
+ * A match is a succession of rounds (Games) on fresh maps, until
+ * a player wins enough rounds
+ */
+pub struct Match {
+    pub game: Game,
+    players: Vec<Player>,
+    bots: Vec<Bot>,
+    left: Vec<u64>,
+    wins: Vec<u32>,
+    settings: GameSettings,
+    rotation: MapRotation,
+    round: u32,
+    tick_rate: u32,
+    state: MatchState,
+    replays_dir: Option<PathBuf>,
+}
+
+impl Match {
+    /**
+     * Create a new Match
+     * @param tick_rate     Ticks per second
+     * @param settings      Settings of the games
+     * @return              The created Match
+     */
+    pub fn new(tick_rate: u32, settings: GameSettings) -> Match {
+        let mut rotation = MapRotation::default();
+        if settings.map_rotation {
+            rotation = MapRotation::load(Path::new(MAPS_DIR));
+            if rotation.is_empty() {
+                warn!("No custom map found in {}, maps will be generated", MAPS_DIR);
+            }
+        }
+        Match {
+            game: Match::new_game(tick_rate, &settings, &mut rotation),
+            players: Vec::new(),
+            bots: Vec::new(),
+            left: Vec::new(),
+            wins: Vec::new(),
+            settings,
+            rotation,
+            round: 1,
+            tick_rate,
+            state: MatchState::Countdown(0),
+            replays_dir: None,
+        }
+    }
+
+    /**
+     * Link a player to the match (and to the current game). Bots are driven by the match
+     * @param player    The player to link
+     * @return          The id of the player in the game
+     */
+    pub fn link_player(&mut self, player: Player) -> Option<u64> {
+        let gid = self.game.link_player(player.clone());
+        if let Some(gid) = gid {
+            if let Some(difficulty) = player.bot {
+                self.bots.push(Bot::new(gid, difficulty, player.rx.clone(), rand::random()));
+            }
+            self.players.push(player);
+            self.wins.push(0);
+        }
+        gid
+    }
+
+    /**
+     * A player leaves the match. A bot plays for it if the room is filled
+     * with bots, else its character dies for the rest of the match
+     * @param gid       The player id in the game
+     * @return          If the operation is successful
+     */
+    pub fn remove_player(&mut self, gid: u64) -> bool {
+        // A bot already plays for players who left a room filled with bots
+        if gid as usize >= self.players.len() || self.left.contains(&gid) || self.players[gid as usize].bot.is_some() {
+            warn!("Can't remove player {} from match", gid);
+            return false;
+        }
+        match self.settings.fill_bots {
+            Some(difficulty) => {
+                // The bot gets its own stream, nobody reads the one of the player anymore
+                let rx: GameStream = Arc::new(Mutex::new(Vec::new()));
+                if let Some(player) = self.game.game_player_to_player.get_mut(&gid) {
+                    player.rx = rx.clone();
+                }
+                let player = &mut self.players[gid as usize];
+                player.bot = Some(difficulty);
+                player.rx = rx.clone();
+                self.bots.push(Bot::new(gid, difficulty, rx, rand::random()));
+            },
+            None => {
+                self.left.push(gid);
+                // Even during the countdown
+                self.game.push_action(Action::Leave, gid);
+            }
+        }
+        true
+    }
+
+    /**
+     * Save the replay of each round in a directory
+     * @param dir   The directory, None to not save replays
+     */
+    pub fn set_replays_dir(&mut self, dir: Option<PathBuf>) {
+        self.replays_dir = dir;
+    }
+
+    /**
+     * Stop the match, the game loop ends on the next tick
+     */
+    pub fn stop(&mut self) {
+        self.state = MatchState::Over;
+    }
+
+    /**
+     * Create the game of a round, on the next custom map if any
+     */
+    fn new_game(tick_rate: u32, settings: &GameSettings, rotation: &mut MapRotation) -> Game {
+        let clock = Box::new(TickClock::new(tick_rate));
+        match rotation.next(settings.players as usize) {
+            Some(file) => {
+                info!("Play custom map {}", file.name);
+                let mut settings = settings.clone();
+                if let Some(drops) = &file.drops {
+                    settings.drops = drops.clone();
+                }
+                let mut map = file.map.clone();
+                map.players.truncate(settings.players as usize);
+                Game::new_with_map(clock, rand::random(), settings, map)
+            },
+            None => Game::new_with_clock(clock, rand::random(), settings.clone()),
+        }
+    }
+
+    /**
+     * Send the map of the first round and start the countdown. A stopped match stays stopped
+     */
+    pub fn start(&mut self) {
+        if self.finished() {
+            return;
+        }
+        self.start_countdown();
+    }
+
+    /**
+     * Send the map to the players, the round starts after the countdown
+     */
+    fn start_countdown(&mut self) {
+        let diff = ServerMessage::Map(MapMsg::new(self.game.map.clone()));
+        self.inform_players(&diff.to_vec());
+        let ticks = COUNTDOWN_DURATION.as_secs() * self.tick_rate as u64;
+        self.state = MatchState::Countdown(ticks);
+    }
+
+    /**
+     * @return the simulation tick rate
+     */
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    /**
+     * Push an action to the current game. Ignored during countdowns and between rounds
+     * @param action    The action
+     * @param gid       The player id in the game
+     */
+    pub fn push_action(&mut self, action: Action, gid: u64) {
+        if let MatchState::Playing = self.state {
+            self.game.push_action(action, gid);
+        }
+    }
+
+    /**
+     * @return if the match is over
+     */
+    pub fn finished(&self) -> bool {
+        matches!(self.state, MatchState::Over)
+    }
+
+    /**
+     * Simulate one tick
+     */
+    pub fn event_loop(&mut self) {
+        // Nobody reads the events of players who left
+        for gid in &self.left {
+            self.players[*gid as usize].rx.lock().unwrap().clear();
+        }
+        match self.state {
+            MatchState::Countdown(ticks_left) => {
+                if ticks_left == 0 {
+                    self.game.start();
+                    self.state = MatchState::Playing;
+                    info!("Round {} started", self.round);
+                    return;
+                }
+                if ticks_left % self.tick_rate as u64 == 0 {
+                    let diff = ServerMessage::Countdown(Countdown {
+                        seconds: ticks_left / self.tick_rate as u64,
+                    });
+                    self.inform_players(&diff.to_vec());
+                }
+                self.state = MatchState::Countdown(ticks_left - 1);
+            },
+            MatchState::Playing => {
+                self.bots_think();
+                self.game.event_loop();
+                if self.game.finished() {
+                    self.end_round();
+                }
+            },
+            MatchState::Intermission(ticks_left) => {
+                if ticks_left == 0 {
+                    self.next_round();
+                    return;
+                }
+                if ticks_left % self.tick_rate as u64 == 0 {
+                    let diff = ServerMessage::Intermission(Intermission {
+                        seconds: ticks_left / self.tick_rate as u64,
+                    });
+                    self.inform_players(&diff.to_vec());
+                }
+                self.state = MatchState::Intermission(ticks_left - 1);
+            },
+            MatchState::Over => {}
+        }
+    }
+
+    fn bots_think(&mut self) {
+        if self.bots.is_empty() {
+            return;
+        }
+        let danger = bot::danger_map(&self.game);
+        let mut actions = Vec::new();
+        for bot in &mut self.bots {
+            if let Some(action) = bot.think(&self.game, &danger) {
+                actions.push((action, bot.gid()));
+            }
+        }
+        for (action, gid) in actions {
+            self.game.push_action(action, gid);
+        }
+    }
+
+    fn end_round(&mut self) {
+        self.save_replay();
+        let winner = self.game.winner();
+        let winning_team = self.game.winning_team();
+        // Every member of the winning team wins the round
+        let mut round_winners = Vec::new();
+        for (gid, player) in self.players.iter().enumerate() {
+            if winner == Some(gid as u64) || (winning_team.is_some() && player.team == winning_team) {
+                round_winners.push(gid);
+            }
+        }
+        for gid in &round_winners {
+            self.wins[*gid] += 1;
+        }
+        info!("Round {} is finished, winner: {:?}, winning team: {:?}", self.round, winner, winning_team);
+        let diff = ServerMessage::RoundOver(RoundOver {
+            round: self.round,
+            winner,
+            winning_team,
+            wins: self.wins.clone(),
+        });
+        self.inform_players(&diff.to_vec());
+
+        let won = round_winners.iter().any(|gid| self.wins[*gid] >= self.settings.rounds_to_win);
+        // Nobody can win rounds alone
+        if won || self.players.len() < 2 {
+            info!("Match is finished, winner: {:?}, winning team: {:?}", winner, winning_team);
+            let diff = ServerMessage::MatchOver(MatchOver {
+                winner,
+                winning_team,
+                wins: self.wins.clone(),
+            });
+            self.inform_players(&diff.to_vec());
+            self.state = MatchState::Over;
+        } else {
+            let ticks = INTERMISSION_DURATION.as_secs() * self.tick_rate as u64;
+            self.state = MatchState::Intermission(ticks);
+        }
+    }
+
+    fn next_round(&mut self) {
+        self.round += 1;
+        self.game = Match::new_game(self.tick_rate, &self.settings, &mut self.rotation);
+        for player in &self.players {
+            self.game.link_player(player.clone());
+        }
+        for bot in &mut self.bots {
+            bot.reset();
+        }
+        for gid in &self.left {
+            self.game.push_action(Action::Leave, *gid);
+        }
+        self.start_countdown();
+    }
+
+    fn inform_players(&self, diff: &[u8]) {
+        for player in &self.players {
+            player.rx.lock().unwrap().push(diff.to_vec());
+        }
+    }
+
+    /**
+     * Write the replay of the finished round in the replays directory, if any
+     */
+    fn save_replay(&self) {
+        let dir = match &self.replays_dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = dir.join(format!("{}-{}.replay", timestamp, self.game.seed()));
+        match self.game.replay().save(&path) {
+            Ok(_) => info!("Replay saved to {}", path.display()),
+            Err(e) => error!("Can't save replay to {}: {}", path.display(), e),
+        }
+    }
+}
\ No newline at end of file
@@ -25,6 +25,25 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
+pub mod bot;
+pub mod clock;
 pub mod game;
+pub mod gamematch;
+pub mod replay;
+#[cfg(test)]
+#[macro_use]
+mod scenario;
+pub mod scheduler;
+pub mod settings;
+pub mod stats;
+#[cfg(test)]
+mod tests;
 
-pub use game::{Action, Game};
\ No newline at end of file
+pub use bot::{Bot, Difficulty};
+pub use clock::{GameClock, TickClock};
+pub use game::{Action, Game, DEFAULT_TICK_RATE};
+pub use gamematch::Match;
+pub use replay::{Replay, ReplayAction, ReplayPlayer};
+pub use scheduler::TickScheduler;
+pub use settings::{GameSettings, MAX_PLAYERS, MIN_PLAYERS};
+pub use stats::PlayerStats;
\ No newline at end of file
@@ -0,0 +1,187 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use super::clock::TickClock;
+use super::game::{Action, Game};
+use super::settings::GameSettings;
+use crate::bomber::core::Player;
+use crate::bomber::core::server::GameStream;
+use crate::bomber::gen::Map;
+
+use rmps::{Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/**
+ * An action pushed by a player during a game
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ReplayAction {
+    pub tick: u64,
+    pub player: u64,
+    pub action: Action,
+}
+
+/**
+ * Everything needed to re-run a game: the seed, the settings, the map if it
+ * was not generated, the players and the inputs
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub tick_rate: u32,
+    pub settings: GameSettings,
+    pub map: Option<Map>,
+    pub names: Vec<String>,
+    pub teams: Vec<Option<u32>>,
+    pub actions: Vec<ReplayAction>,
+}
+
+impl Replay {
+    /**
+     * Create an empty replay
+     * @param seed          The seed of the game
+     * @param tick_rate     The tick rate of the game
+     * @param settings      The settings of the game
+     * @return              The created Replay
+     */
+    pub fn new(seed: u64, tick_rate: u32, settings: GameSettings) -> Replay {
+        Replay {
+            seed,
+            tick_rate,
+            settings,
+            map: None,
+            names: Vec::new(),
+            teams: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    /**
+     * Write the replay as msgpack
+     * @param path  The file to write
+     * @return      If the operation is successful
+     */
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.serialize(&mut Serializer::new(&mut writer))
+            .map_err(io::Error::other)
+    }
+
+    /**
+     * Read a replay written by save()
+     * @param path  The file to read
+     * @return      The replay
+     */
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut de = Deserializer::new(reader);
+        Deserialize::deserialize(&mut de)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/**
+ * Re-run a game from a Replay and emit the same diffs as the original game
+ */
+pub struct ReplayPlayer {
+    pub game: Game,
+    actions: Vec<ReplayAction>,
+    next_action: usize,
+    streams: Vec<GameStream>,
+}
+
+impl ReplayPlayer {
+    /**
+     * Prepare a game from a replay
+     * @param replay    The replay to play
+     * @return          The created ReplayPlayer
+     */
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        let clock = Box::new(TickClock::new(replay.tick_rate));
+        let mut game = match replay.map {
+            Some(map) => Game::new_with_map(clock, replay.seed, replay.settings, map),
+            None => Game::new_with_clock(clock, replay.seed, replay.settings),
+        };
+        let mut streams = Vec::new();
+        for (idx, name) in replay.names.into_iter().enumerate() {
+            let rx: GameStream = Arc::new(Mutex::new(Vec::new()));
+            game.link_player(Player {
+                rx: rx.clone(),
+                name,
+                team: replay.teams.get(idx).cloned().unwrap_or(None),
+                bot: None,
+                ready: true,
+            });
+            streams.push(rx);
+        }
+        game.start();
+        ReplayPlayer {
+            game,
+            actions: replay.actions,
+            next_action: 0,
+            streams,
+        }
+    }
+
+    /**
+     * Simulate one tick of the replay
+     * @return  The diffs emitted during this tick (as seen by the first player)
+     */
+    pub fn step(&mut self) -> Vec<Vec<u8>> {
+        let tick = self.game.tick();
+        while self.next_action < self.actions.len() && self.actions[self.next_action].tick <= tick {
+            let action = &self.actions[self.next_action];
+            self.game.push_action(action.action.clone(), action.player);
+            self.next_action += 1;
+        }
+        self.game.event_loop();
+
+        let mut diffs = Vec::new();
+        for (idx, stream) in self.streams.iter().enumerate() {
+            let mut rx = stream.lock().unwrap();
+            if idx == 0 {
+                diffs = rx.clone();
+            }
+            *rx = Vec::new();
+        }
+        diffs
+    }
+
+    /**
+     * @return if the replayed game is finished
+     */
+    pub fn finished(&self) -> bool {
+        self.game.finished()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,258 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rmps::Deserializer;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::clock::TickClock;
+use super::game::{Action, Bomb, Game, DEFAULT_TICK_RATE};
+use super::settings::GameSettings;
+use crate::bomber::core::Player;
+use crate::bomber::core::server::GameStream;
+use crate::bomber::gen::Map;
+use crate::bomber::gen::item::InteractiveItem;
+use crate::bomber::gen::utils::{MapPlayer, Shape};
+use crate::bomber::net::msg::ServerMessage;
+
+/**
+ * Decode the emitted diffs of a type, in emission order
+ * e.g. diffs!(scenario, PlayerDie) gives a Vec<PlayerDie>
+ */
+macro_rules! diffs {
+    ($scenario:expr, $variant:ident) => {
+        $scenario.diffs().into_iter().filter_map(|diff| match diff {
+            ServerMessage::$variant(diff) => Some(diff),
+            _ => None,
+        }).collect::<Vec<_>>()
+    };
+}
+
+/**
+ * Headless simulation of a Game, used by scenario tests.
+ * A scenario is built from an ASCII map read by `Map::from_str`:
+ *   X empty, B block, H water, N/S/W/E walls, D box, O bonus, M malus,
+ *   P player, b bomb, p player on a bomb, u unknown item (empty square)
+ * Players are numbered in reading order. Bonuses are MoreBombs, maluses are Slow
+ * and bombs belong to the first player unless a player is on it.
+ * Use Scenario::item to place other items.
+ */
+pub struct Scenario {
+    pub game: Game,
+    streams: Vec<GameStream>,
+    script: BTreeMap<u64, Vec<(u64, Action)>>,
+    diffs: Vec<Vec<u8>>,
+}
+
+impl Scenario {
+    /**
+     * Create a scenario with default settings
+     * @param ascii     The map
+     * @return          The created Scenario
+     */
+    pub fn new(ascii: &str) -> Scenario {
+        Scenario::with_settings(ascii, GameSettings::default())
+    }
+
+    /**
+     * Create a scenario
+     * @param ascii     The map
+     * @param settings  The settings of the game
+     * @return          The created Scenario
+     */
+    pub fn with_settings(ascii: &str, settings: GameSettings) -> Scenario {
+        let map: Map = ascii.parse().unwrap();
+        let names: Vec<String> = (0..map.players.len()).map(|idx| format!("Player {}", idx)).collect();
+        Scenario::build(map, settings, names)
+    }
+
+    /**
+     * Create a scenario with default settings and named players
+     * @param ascii     The map
+     * @param names     The name of each player, in reading order
+     * @return          The created Scenario
+     */
+    pub fn with_names(ascii: &str, names: &[&str]) -> Scenario {
+        let map: Map = ascii.parse().unwrap();
+        Scenario::build(map, GameSettings::default(), names.iter().map(|name| name.to_string()).collect())
+    }
+
+    fn build(map: Map, settings: GameSettings, names: Vec<String>) -> Scenario {
+        let bombs: Vec<(usize, usize)> = (0..map.items.len())
+            .filter(|idx| map.items[*idx].as_ref().map_or(false, |i| i.name() == "Bomb"))
+            .map(|idx| (idx % map.w, idx / map.w))
+            .collect();
+        let clock = Box::new(TickClock::new(DEFAULT_TICK_RATE));
+        let fuse = settings.bomb_fuse();
+        let mut game = Game::new_with_map(clock, 0, settings, map);
+        let mut streams = Vec::new();
+        for name in names {
+            let rx: GameStream = Arc::new(Mutex::new(Vec::new()));
+            game.link_player(Player {
+                rx: rx.clone(),
+                name,
+                team: None,
+                bot: None,
+                ready: true,
+            });
+            streams.push(rx);
+        }
+        for pos in bombs {
+            let creator_id = game.map.players.iter()
+                .position(|p| (p.x as usize, p.y as usize) == pos)
+                .unwrap_or(0);
+            game.bombs.push(Bomb {
+                creator_id: creator_id as u32,
+                radius: game.map.players[creator_id].radius as usize,
+                shape: Shape::Cross,
+                created_time: Duration::from_secs(0),
+                duration: fuse,
+                pos: (pos.0 as f32 + 0.5, pos.1 as f32 + 0.5),
+                exploding_info: None,
+                moving_dir: None,
+            });
+        }
+        game.start();
+        let mut scenario = Scenario {
+            game,
+            streams,
+            script: BTreeMap::new(),
+            diffs: Vec::new(),
+        };
+        scenario.collect_diffs();
+        scenario
+    }
+
+    /**
+     * Place an item on the map
+     * @param x     The square's x
+     * @param y     The square's y
+     * @param item  The item
+     */
+    pub fn item(&mut self, x: usize, y: usize, item: InteractiveItem) -> &mut Scenario {
+        let w = self.game.map.w;
+        self.game.map.items[x + y * w] = Some(item);
+        self
+    }
+
+    /**
+     * Script an action
+     * @param tick      The tick when the action is pushed
+     * @param gid       The player
+     * @param action    The action
+     */
+    pub fn act(&mut self, tick: u64, gid: u64, action: Action) -> &mut Scenario {
+        self.script.entry(tick).or_default().push((gid, action));
+        self
+    }
+
+    /**
+     * Script an action repeated at each tick, like a key held down
+     * @param from      The first tick
+     * @param ticks     Number of ticks to hold the action
+     * @param gid       The player
+     * @param action    The action
+     */
+    pub fn hold(&mut self, from: u64, ticks: u64, gid: u64, action: Action) -> &mut Scenario {
+        for tick in from..(from + ticks) {
+            self.act(tick, gid, action.clone());
+        }
+        self
+    }
+
+    /**
+     * Simulate some ticks
+     * @param ticks     Number of ticks to simulate
+     */
+    pub fn run(&mut self, ticks: u64) -> &mut Scenario {
+        for _ in 0..ticks {
+            let tick = self.game.tick();
+            if let Some(actions) = self.script.remove(&tick) {
+                for (gid, action) in actions {
+                    self.game.push_action(action, gid);
+                }
+            }
+            self.game.event_loop();
+            self.collect_diffs();
+        }
+        self
+    }
+
+    /**
+     * Simulate a duration of game
+     * @param duration  The duration to simulate
+     */
+    pub fn run_for(&mut self, duration: Duration) -> &mut Scenario {
+        let ticks = (duration.as_secs_f64() * self.game.tick_rate() as f64).round() as u64;
+        self.run(ticks)
+    }
+
+    /**
+     * Every player receives the game diffs, keep the ones of the first player
+     */
+    fn collect_diffs(&mut self) {
+        for (idx, stream) in self.streams.iter().enumerate() {
+            let mut stream = stream.lock().unwrap();
+            if idx == 0 {
+                self.diffs.append(&mut stream);
+            }
+            stream.clear();
+        }
+    }
+
+    /**
+     * @return the map as printed by `impl Display for Map`
+     */
+    pub fn ascii(&self) -> String {
+        format!("{}", self.game.map)
+    }
+
+    /**
+     * @return the character printed for a square
+     */
+    pub fn square(&self, x: usize, y: usize) -> char {
+        self.ascii().lines().nth(y).unwrap().chars().nth(x).unwrap()
+    }
+
+    pub fn player(&self, gid: u64) -> &MapPlayer {
+        &self.game.map.players[gid as usize]
+    }
+
+    /**
+     * Decode the emitted diffs, see diffs! to get the diffs of a type
+     * @return          The diffs, in emission order
+     */
+    pub fn diffs(&self) -> Vec<ServerMessage> {
+        self.diffs.iter().map(|diff| {
+            let mut de = Deserializer::new(Cursor::new(&diff[..]));
+            Deserialize::deserialize(&mut de).unwrap()
+        }).collect()
+    }
+}
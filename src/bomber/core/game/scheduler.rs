@@ -0,0 +1,97 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/**
+ * Maximum ticks simulated in one go when the scheduler is late.
+ * Beyond that, late ticks are dropped to avoid a spiral of death.
+ */
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+/**
+ * Drive a simulation at a fixed tick rate, independently of the CPU speed
+ */
+pub struct TickScheduler {
+    tick_duration: Duration,
+    next_tick: Instant,
+}
+
+impl TickScheduler {
+    /**
+     * Create a new scheduler
+     * @param tick_rate     Ticks per second
+     * @return              The created TickScheduler
+     */
+    pub fn new(tick_rate: u32) -> TickScheduler {
+        TickScheduler::starting_at(tick_rate, Instant::now())
+    }
+
+    /**
+     * Create a new scheduler with a given first tick
+     * @param tick_rate     Ticks per second
+     * @param start         When the first tick is due
+     * @return              The created TickScheduler
+     */
+    pub fn starting_at(tick_rate: u32, start: Instant) -> TickScheduler {
+        TickScheduler {
+            tick_duration: Duration::from_secs(1) / tick_rate.max(1),
+            next_tick: start,
+        }
+    }
+
+    /**
+     * Sleep until the next tick is due
+     * @return  The number of ticks to simulate (more than one if late)
+     */
+    pub fn wait(&mut self) -> u32 {
+        let now = Instant::now();
+        if self.next_tick > now {
+            thread::sleep(self.next_tick - now);
+        }
+        self.ticks_at(Instant::now())
+    }
+
+    /**
+     * Count the ticks due at a given time, and schedule the next one
+     * @param now   The current time
+     * @return      The number of ticks to simulate (more than one if late)
+     */
+    pub fn ticks_at(&mut self, now: Instant) -> u32 {
+        let mut ticks = 0;
+        while self.next_tick <= now && ticks < MAX_CATCH_UP_TICKS {
+            self.next_tick += self.tick_duration;
+            ticks += 1;
+        }
+        if self.next_tick <= now {
+            warn!("Simulation is late, dropping ticks");
+            self.next_tick = now + self.tick_duration;
+        }
+        ticks
+    }
+}
\ No newline at end of file
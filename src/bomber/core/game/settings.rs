@@ -0,0 +1,160 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use super::bot::Difficulty;
+use crate::bomber::gen::generator::generator_by_name;
+use crate::bomber::gen::item::DropTable;
+use std::time::Duration;
+
+pub const MIN_PLAYERS: u32 = 2;
+pub const MAX_PLAYERS: u32 = 16;
+
+/**
+ * Settings of the games played in a room, chosen by the room owner
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct GameSettings {
+    pub players: u32,
+    pub generator: String,      /* classic, mirrored, maze, cave or arena */
+    pub width: usize,
+    pub height: usize,
+    pub duration: u64,          /* seconds */
+    pub sudden_death: u64,      /* seconds before the end when the map starts to shrink */
+    pub bombs: u32,
+    pub radius: u32,
+    pub speed_factor: u32,
+    pub bomb_fuse: u64,         /* milliseconds */
+    pub malus_duration: u64,    /* seconds */
+    pub rounds_to_win: u32,
+    pub teams: u32,             /* 0 for free for all */
+    pub friendly_fire: bool,
+    pub fill_bots: Option<Difficulty>,  /* fill empty slots with bots when launching */
+    pub drops: DropTable,               /* items left by boxes, custom maps can override it */
+    pub map_rotation: bool,             /* play the custom maps of the server */
+}
+
+impl Default for GameSettings {
+    fn default() -> GameSettings {
+        GameSettings {
+            players: 4,
+            generator: String::from("classic"),
+            width: 13,
+            height: 11,
+            duration: 180,
+            sudden_death: 30,
+            bombs: 1,
+            radius: 2,
+            speed_factor: 1000,
+            bomb_fuse: 3000,
+            malus_duration: 10,
+            rounds_to_win: 3,
+            teams: 0,
+            friendly_fire: true,
+            fill_bots: None,
+            drops: DropTable::default(),
+            map_rotation: false,
+        }
+    }
+}
+
+impl GameSettings {
+    /**
+     * Check that settings are playable
+     * @return  An explanation if settings are invalid
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        if self.players < MIN_PLAYERS || self.players > MAX_PLAYERS {
+            return Err(format!("Players must be between {} and {}", MIN_PLAYERS, MAX_PLAYERS));
+        }
+        if generator_by_name(&self.generator).is_none() {
+            return Err(format!("Unknown map generator {}", self.generator));
+        }
+        if self.width < 11 || self.width > 51 || self.height < 11 || self.height > 51 {
+            return Err(String::from("Map size must be between 11x11 and 51x51"));
+        }
+        if self.duration < 30 || self.duration > 3600 {
+            return Err(String::from("Duration must be between 30 and 3600 seconds"));
+        }
+        // Sudden death is the only way to end a game with survivors
+        if self.sudden_death < 1 || self.sudden_death > self.duration {
+            return Err(String::from("Sudden death must last between 1 second and the whole game"));
+        }
+        if self.bombs < 1 || self.bombs > 10 {
+            return Err(String::from("Starting bombs must be between 1 and 10"));
+        }
+        if self.radius < 1 || self.radius > 20 {
+            return Err(String::from("Starting radius must be between 1 and 20"));
+        }
+        if self.speed_factor < 100 || self.speed_factor > 5000 {
+            return Err(String::from("Starting speed must be between 100 and 5000"));
+        }
+        if self.bomb_fuse < 500 || self.bomb_fuse > 10000 {
+            return Err(String::from("Bomb fuse must be between 500 and 10000 ms"));
+        }
+        if self.malus_duration < 1 || self.malus_duration > 120 {
+            return Err(String::from("Malus duration must be between 1 and 120 seconds"));
+        }
+        if self.rounds_to_win < 1 || self.rounds_to_win > 10 {
+            return Err(String::from("Rounds to win must be between 1 and 10"));
+        }
+        if self.teams == 1 {
+            return Err(String::from("A team game needs at least 2 teams"));
+        }
+        if self.teams > self.players {
+            return Err(String::from("There can't be more teams than players"));
+        }
+        self.drops.validate()
+    }
+
+    /**
+     * The map grows with the number of players, so each player keeps
+     * the room it has in a classic 13x11 map for 4 players
+     * @return  The size (width, height) of the map to generate
+     */
+    pub fn map_size(&self) -> (usize, usize) {
+        let scale = (self.players.max(4) as f32 / 4.).sqrt();
+        // Odd sizes keep the blocks grid symmetric
+        let scaled = |size: f32| (size * scale).round() as usize | 1;
+        (self.width.max(scaled(13.)), self.height.max(scaled(11.)))
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration)
+    }
+
+    pub fn sudden_death(&self) -> Duration {
+        Duration::from_secs(self.sudden_death)
+    }
+
+    pub fn bomb_fuse(&self) -> Duration {
+        Duration::from_millis(self.bomb_fuse)
+    }
+
+    pub fn malus_duration(&self) -> Duration {
+        Duration::from_secs(self.malus_duration)
+    }
+}
\ No newline at end of file
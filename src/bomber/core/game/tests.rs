@@ -0,0 +1,466 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rmps::Deserializer;
+use serde::Deserialize;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::bot::{self, Bot, Difficulty};
+use super::clock::TickClock;
+use super::game::{Action, Game, DEFAULT_TICK_RATE};
+use super::replay::{Replay, ReplayPlayer};
+use super::scenario::Scenario;
+use super::scheduler::TickScheduler;
+use super::settings::{GameSettings, MAX_PLAYERS};
+use crate::bomber::core::Player;
+use crate::bomber::gen::item::Bonus;
+use crate::bomber::gen::utils::{Direction, Shape, SquareType};
+use crate::bomber::net::msg::ServerMessage;
+
+#[test]
+fn walls_block_players_from_their_side() {
+    let mut scenario = Scenario::new("
+        PWX
+        PEX
+    ");
+    scenario
+        .hold(0, 10, 0, Action::Move(Direction::East))
+        .hold(0, 10, 1, Action::Move(Direction::East))
+        .run(10);
+    // The west wall blocks players coming from the west, the east wall doesn't
+    assert!(scenario.player(0).x < 1.);
+    assert!(scenario.player(1).x >= 1.);
+}
+
+#[test]
+fn players_pick_bonus_and_malus() {
+    let mut scenario = Scenario::new("
+        POMX
+        XXXP
+    ");
+    scenario.hold(0, 20, 0, Action::Move(Direction::East)).run(20);
+    assert_eq!(scenario.player(0).bomb, 2);
+    assert_eq!(scenario.square(1, 0), 'X');
+    assert_eq!(scenario.square(2, 0), 'P');
+    assert_eq!(scenario.game.stats[0].items, 2);
+    let destroyed = diffs!(scenario, DestroyItem);
+    assert_eq!(destroyed.iter().map(|d| (d.w, d.h)).collect::<Vec<_>>(), vec![(1, 0), (2, 0)]);
+    // Slowed down by the malus
+    let x = scenario.player(0).x;
+    scenario.hold(20, 4, 0, Action::Move(Direction::East)).run(4);
+    assert!(scenario.player(0).x - x < 0.2);
+}
+
+#[test]
+fn bombs_break_boxes_and_kill_players() {
+    let mut scenario = Scenario::new("
+        PXD
+        XBX
+        XXP
+    ");
+    scenario.act(0, 0, Action::PutBomb).run_for(Duration::from_secs(4));
+    // The box may drop an item
+    assert_ne!(scenario.square(2, 0), 'D');
+    let deaths = diffs!(scenario, PlayerDie);
+    assert_eq!(deaths.len(), 1);
+    assert_eq!(deaths[0].id, 0);
+    assert!(deaths[0].suicide);
+    let game_over = diffs!(scenario, GameOver);
+    assert_eq!(game_over.len(), 1);
+    assert_eq!(game_over[0].winner, Some(1));
+    assert_eq!(game_over[0].scoreboard[1].name, "Player 1");
+}
+
+#[test]
+fn square_bombs_reach_the_corners() {
+    let mut scenario = Scenario::new("
+        POXXXXX
+        XDXXXDX
+        XXXXXXX
+        XXXbXXX
+        XXXXXXX
+        XDXXXDX
+        XXXXXXP
+    ");
+    scenario.item(1, 0, Box::new(Bonus::SquareBombs));
+    scenario.hold(0, 10, 0, Action::Move(Direction::East)).run(10);
+    assert_eq!(scenario.game.bomb_shape(0), Shape::Square);
+    scenario.game.bombs[0].shape = Shape::Square;
+    scenario.run_for(Duration::from_secs(4));
+    for (x, y) in vec![(1, 1), (5, 1), (1, 5), (5, 5)] {
+        assert_ne!(scenario.square(x, y), 'D');
+    }
+    assert!(!scenario.player(0).dead && !scenario.player(1).dead);
+}
+
+#[test]
+fn circle_bombs_round_the_corners() {
+    let mut scenario = Scenario::new("
+        POXXXXX
+        XDXXXXX
+        XXDXXDX
+        XXXbXXX
+        XXXXXXX
+        XXXXXXX
+        XXXXXXP
+    ");
+    scenario.item(1, 0, Box::new(Bonus::CircleBombs));
+    scenario.hold(0, 10, 0, Action::Move(Direction::East)).run(10);
+    assert_eq!(scenario.game.bomb_shape(0), Shape::Circle);
+    scenario.game.bombs[0].shape = Shape::Circle;
+    scenario.run_for(Duration::from_secs(4));
+    assert_ne!(scenario.square(2, 2), 'D');
+    assert_ne!(scenario.square(5, 2), 'D');
+    // Out of the circle, where a square bomb would reach
+    assert_eq!(scenario.square(1, 1), 'D');
+    assert!(!scenario.player(0).dead && !scenario.player(1).dead);
+}
+
+#[test]
+fn players_repel_bombs() {
+    let mut scenario = Scenario::new("
+        PXXbXXXX
+        XXXXXXXP
+    ");
+    scenario.item(1, 0, Box::new(Bonus::RepelBombs));
+    scenario.hold(0, 30, 0, Action::Move(Direction::East)).run(60);
+    assert!(!diffs!(scenario, BombMove).is_empty());
+    assert!(scenario.game.bombs[0].pos.0 > 4.);
+    assert_eq!(scenario.square(3, 0), 'X');
+}
+
+#[test]
+fn flames_chain_bombs() {
+    let mut scenario = Scenario::new("
+        PXbXPX
+        XXXXXX
+        XXXXXX
+        XXXXXX
+    ");
+    scenario
+        .hold(0, 40, 0, Action::Move(Direction::South))
+        .act(60, 1, Action::PutBomb)
+        .hold(61, 40, 1, Action::Move(Direction::South));
+    // The first bomb detonates the second one as soon as its flames reach it
+    while diffs!(scenario, BombChain).is_empty() {
+        assert!(diffs!(scenario, BombExplode).len() < 2);
+        scenario.run(1);
+    }
+    let explosions = diffs!(scenario, BombExplode);
+    assert_eq!(explosions.len(), 2);
+    assert_eq!((explosions[0].w, explosions[0].h), (2, 0));
+    assert_eq!((explosions[1].w, explosions[1].h), (4, 0));
+    let chains = diffs!(scenario, BombChain);
+    assert_eq!(chains.len(), 1);
+    assert_eq!((chains[0].from_w, chains[0].from_h, chains[0].w, chains[0].h), (2, 0, 4, 0));
+    // The second bomb doesn't explode again at the end of its own fuse
+    scenario.run_for(Duration::from_secs(4));
+    assert_eq!(diffs!(scenario, BombExplode).len(), 2);
+    assert_eq!(diffs!(scenario, BombChain).len(), 1);
+    assert!(!scenario.player(0).dead && !scenario.player(1).dead);
+}
+
+#[test]
+fn punched_bombs_fly_over_boxes() {
+    let mut scenario = Scenario::new("
+        PObDDDXX
+        XXXXXXXP
+    ");
+    scenario.item(1, 0, Box::new(Bonus::PunchBombs));
+    scenario
+        .hold(0, 20, 0, Action::Move(Direction::East))
+        .act(20, 0, Action::Punch(Direction::East))
+        .run(21);
+    // The bomb can't land on the box 3 squares away and bounces to the next square
+    let punched = diffs!(scenario, BombPunched);
+    assert_eq!(punched.len(), 1);
+    assert_eq!((punched[0].old_x, punched[0].old_y), (2.5, 0.5));
+    assert_eq!((punched[0].x, punched[0].y), (6.5, 0.5));
+    assert_eq!(scenario.square(2, 0), 'X');
+    assert_eq!(scenario.square(5, 0), 'D');
+    assert_eq!(scenario.square(6, 0), 'b');
+    assert_eq!(scenario.game.bombs[0].pos, (6.5, 0.5));
+}
+
+#[test]
+fn punched_bombs_wrap_around_the_map() {
+    let mut scenario = Scenario::new("
+        XbOPXX
+        XXXXPX
+    ");
+    scenario.item(2, 0, Box::new(Bonus::PunchBombs));
+    scenario
+        .hold(0, 20, 0, Action::Move(Direction::West))
+        .act(20, 0, Action::Punch(Direction::West))
+        .run(21);
+    let punched = diffs!(scenario, BombPunched);
+    assert_eq!(punched.len(), 1);
+    assert_eq!((punched[0].x, punched[0].y), (4.5, 0.5));
+    assert_eq!(scenario.square(1, 0), 'X');
+    assert_eq!(scenario.square(4, 0), 'b');
+    // Without the bonus, the bomb can't be punched back
+    scenario.act(21, 1, Action::Punch(Direction::North)).run(1);
+    assert_eq!(diffs!(scenario, BombPunched).len(), 1);
+}
+
+#[test]
+fn sudden_death_fills_the_map() {
+    let mut settings = GameSettings::default();
+    settings.duration = 30;
+    settings.sudden_death = 30;
+    let mut scenario = Scenario::with_settings("
+        PXX
+        XXX
+        XXP
+    ", settings);
+    scenario.run_for(Duration::from_secs(30));
+    assert_eq!(scenario.ascii(), "BBB\nBBB\nBBB\n");
+    assert!(scenario.player(0).dead && scenario.player(1).dead);
+    assert_eq!(diffs!(scenario, UpdateSquare).len(), 9);
+}
+
+/**
+ * Players 0 and 2 play against player 1, player 2 stands next to a bomb of player 0
+ */
+fn team_scenario(friendly_fire: bool) -> Scenario {
+    let settings = GameSettings { friendly_fire, ..Default::default() };
+    let mut scenario = Scenario::with_settings("
+        PXXXX
+        XXXXX
+        XXXXX
+        PXXbP
+        XXXXX
+    ", settings);
+    for (gid, team) in vec![0, 1, 0].into_iter().enumerate() {
+        scenario.game.map.players[gid].team = Some(team);
+    }
+    scenario
+}
+
+#[test]
+fn teammates_are_spared_without_friendly_fire() {
+    let mut scenario = team_scenario(false);
+    scenario.run_for(Duration::from_secs(4));
+    assert!(diffs!(scenario, PlayerDie).is_empty());
+    assert!(!scenario.player(2).dead);
+    assert!(!scenario.game.finished());
+}
+
+#[test]
+fn teammates_are_killed_with_friendly_fire() {
+    let mut scenario = team_scenario(true);
+    scenario.run_for(Duration::from_secs(4));
+    let deaths = diffs!(scenario, PlayerDie);
+    assert_eq!(deaths.len(), 1);
+    assert_eq!((deaths[0].id, deaths[0].killer, deaths[0].suicide), (2, Some(0), false));
+    // The team still has a player alive
+    assert!(!scenario.game.finished());
+}
+
+#[test]
+fn last_team_standing_wins() {
+    let mut scenario = team_scenario(false);
+    // The only player of the other team walks into the blast
+    scenario.hold(0, 60, 1, Action::Move(Direction::East)).run_for(Duration::from_secs(4));
+    assert!(scenario.player(1).dead);
+    assert!(scenario.game.finished());
+    assert_eq!(scenario.game.winner(), None);
+    assert_eq!(scenario.game.winning_team(), Some(0));
+    let game_over = diffs!(scenario, GameOver);
+    assert_eq!(game_over.len(), 1);
+    assert_eq!((game_over[0].winner, game_over[0].winning_team, game_over[0].draw), (None, Some(0), false));
+}
+
+/**
+ * Let a bot play in a scenario
+ */
+fn run_with_bot(scenario: &mut Scenario, bot: &mut Bot, ticks: u64) {
+    for _ in 0..ticks {
+        let danger = bot::danger_map(&scenario.game);
+        let tick = scenario.game.tick();
+        if let Some(action) = bot.think(&scenario.game, &danger) {
+            scenario.act(tick, bot.gid(), action);
+        }
+        scenario.run(1);
+    }
+}
+
+#[test]
+fn bots_flee_bombs() {
+    for difficulty in vec![Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+        let mut scenario = Scenario::new("
+            PXXXX
+            XXXXX
+            XXbPX
+            XXXXX
+        ");
+        let mut bot = Bot::new(1, difficulty, Arc::new(Mutex::new(Vec::new())), 0);
+        run_with_bot(&mut scenario, &mut bot, 4 * DEFAULT_TICK_RATE as u64);
+        assert_eq!(diffs!(scenario, BombExplode).len(), 1);
+        assert!(!scenario.player(1).dead, "{:?} bot didn't flee", difficulty);
+    }
+}
+
+#[test]
+fn bots_find_their_way_to_bonuses() {
+    let mut scenario = Scenario::new("
+        PXXXXXX
+        BBBBBBX
+        OXXXXXX
+        BBBBBBB
+        XXXXXXP
+    ");
+    let mut bot = Bot::new(0, Difficulty::Hard, Arc::new(Mutex::new(Vec::new())), 0);
+    run_with_bot(&mut scenario, &mut bot, 10 * DEFAULT_TICK_RATE as u64);
+    // The bonus gives one more bomb
+    assert_eq!(scenario.player(0).bomb, 2);
+    assert_eq!(scenario.square(0, 2), 'P');
+}
+
+#[test]
+fn players_leaving_lose_the_game() {
+    let mut scenario = Scenario::new("
+        PXXP
+    ");
+    scenario.act(1, 1, Action::Leave).run(5);
+    assert!(scenario.player(1).dead);
+    let deaths = diffs!(scenario, PlayerDie);
+    assert_eq!(deaths.len(), 1);
+    assert_eq!(deaths[0].killer, None);
+    assert!(scenario.game.finished());
+    assert_eq!(scenario.game.winner(), Some(0));
+}
+
+#[test]
+fn blasts_are_reproducible() {
+    let map = "
+        PXDXX
+        XDbDX
+        XXDXX
+        XXXXP
+    ";
+    let mut first = Scenario::new(map);
+    first.run_for(Duration::from_secs(4));
+    let mut second = Scenario::new(map);
+    second.run_for(Duration::from_secs(4));
+    for (x, y) in vec![(2, 0), (1, 1), (3, 1), (2, 2)] {
+        assert_ne!(first.square(x, y), 'D');
+    }
+    // Boxes roll their drops in the same order
+    assert_eq!(first.diffs(), second.diffs());
+    assert_eq!(first.ascii(), second.ascii());
+}
+
+#[test]
+fn replays_play_the_game_again() {
+    let mut scenario = Scenario::with_names("
+        PXDXX
+        XXDXX
+        XDDXX
+        XXXXP
+    ", &["alice", "bob"]);
+    scenario
+        .act(0, 0, Action::PutBomb)
+        .hold(1, 20, 0, Action::Move(Direction::South))
+        .act(30, 1, Action::PutBomb)
+        .hold(31, 20, 1, Action::Move(Direction::West))
+        .run_for(Duration::from_secs(6));
+
+    let path = std::env::temp_dir().join(format!("bomberust-test-{}.replay", std::process::id()));
+    scenario.game.replay().save(&path).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(&replay, scenario.game.replay());
+    assert_eq!(replay.names, vec!["alice", "bob"]);
+
+    let mut player = ReplayPlayer::new(replay);
+    let mut diffs = Vec::new();
+    while player.game.tick() < scenario.game.tick() {
+        diffs.append(&mut player.step());
+    }
+    let diffs: Vec<ServerMessage> = diffs.iter()
+        .map(|diff| Deserialize::deserialize(&mut Deserializer::new(Cursor::new(&diff[..]))).unwrap())
+        .collect();
+    assert_eq!(diffs, scenario.diffs());
+    assert_eq!(format!("{}", player.game.map), scenario.ascii());
+}
+
+#[test]
+fn games_without_sudden_death_are_rejected() {
+    let mut settings = GameSettings::default();
+    assert!(settings.validate().is_ok());
+    settings.sudden_death = 0;
+    assert!(settings.validate().is_err());
+    settings.sudden_death = settings.duration + 1;
+    assert!(settings.validate().is_err());
+}
+
+#[test]
+fn sudden_death_fills_the_largest_maps() {
+    let mut settings = GameSettings::default();
+    settings.width = 51;
+    settings.height = 51;
+    settings.players = MAX_PLAYERS;
+    settings.duration = 30;
+    settings.sudden_death = 30;
+    assert!(settings.validate().is_ok());
+    let clock = Box::new(TickClock::new(DEFAULT_TICK_RATE));
+    let mut game = Game::new_with_clock(clock, 0, settings.clone());
+    for idx in 0..MAX_PLAYERS {
+        game.link_player(Player {
+            rx: Arc::new(Mutex::new(Vec::new())),
+            name: format!("Player {}", idx),
+            team: None,
+            bot: None,
+            ready: true,
+        });
+    }
+    game.start();
+    for _ in 0..settings.duration * DEFAULT_TICK_RATE as u64 {
+        game.event_loop();
+    }
+    assert!(game.map.squares.iter().all(|sq| sq.sq_type == SquareType::Block));
+    assert!(game.map.players.iter().all(|p| p.dead));
+    assert!(game.finished());
+}
+
+#[test]
+fn schedulers_catch_up_late_ticks() {
+    let start = Instant::now();
+    let tick = Duration::from_millis(20);
+    let mut scheduler = TickScheduler::starting_at(50, start);
+    assert_eq!(scheduler.ticks_at(start), 1);
+    assert_eq!(scheduler.ticks_at(start + tick / 2), 0);
+    assert_eq!(scheduler.ticks_at(start + tick * 3), 3);
+    // Too late, ticks are dropped and the schedule starts again from now
+    let now = start + tick * 100;
+    assert_eq!(scheduler.ticks_at(now), 5);
+    assert_eq!(scheduler.ticks_at(now), 0);
+    assert_eq!(scheduler.ticks_at(now + tick), 1);
+}
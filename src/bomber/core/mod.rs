@@ -30,6 +30,9 @@ pub mod room;
 pub mod server;
 pub mod game;
 
-pub use player::Player;
-pub use room::Room;
+#[cfg(test)]
+mod tests;
+
+pub use player::{validate_name, Player, MAX_NAME_LENGTH};
+pub use room::{Room, RoomOptions};
 pub use server::Server;
\ No newline at end of file
@@ -25,9 +25,36 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
+use super::game::Difficulty;
 use super::server::GameStream;
 
+// Longest display name, in characters
+pub const MAX_NAME_LENGTH: usize = 16;
+
 #[derive(Clone)]
 pub struct Player {
-    pub rx: GameStream
+    pub rx: GameStream,
+    pub name: String,
+    pub team: Option<u32>,
+    pub bot: Option<Difficulty>,    /* None for humans */
+    pub ready: bool,                /* waiting for the game to start, bots are always ready */
+}
+
+/**
+ * Check the display name chosen by a player
+ * @param name  The name
+ * @return      The name without surrounding spaces, or why it is invalid
+ */
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("Name is empty"));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("Name is longer than {} characters", MAX_NAME_LENGTH));
+    }
+    if name.chars().any(|c| c.is_control()) {
+        return Err(String::from("Name contains control characters"));
+    }
+    Ok(name.to_string())
 }
\ No newline at end of file
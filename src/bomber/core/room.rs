@@ -24,26 +24,48 @@
  * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
-use super::Player;
-use super::game::{Action, Game};
+use super::{Player, MAX_NAME_LENGTH};
+use super::game::{Action, Difficulty, GameSettings, Match, TickScheduler, DEFAULT_TICK_RATE, MIN_PLAYERS};
 use super::server::GameStream;
 use super::super::gen::utils::Direction;
 
+use crate::bomber::net::diff_msg::{PlayerIdentity, SerializedEvent};
 use crate::bomber::net::msg::*;
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::thread;
-use std::time::Duration;
 use std::sync::{Arc, Mutex};
 
+// Bots ids can't collide with streams ids
+const FIRST_BOT_ID: u64 = 1 << 63;
+
+/**
+ * Options chosen by the creator of a room
+ */
+#[derive(Clone, Debug, Default)]
+pub struct RoomOptions {
+    pub name: Option<String>,       /* named after the creator if None */
+    pub password: Option<String>,
+    pub private: bool,              /* hidden from the rooms list */
+}
+
 /**
- * Represents a room, manage a Game
+ * Represents a room, manage a Match
  */
 pub struct Room {
+    pub name: String,
+    pub code: String,               /* invite code, ids are guessable */
+    pub password: Option<String>,
+    pub private: bool,
+    pub owner: u64,
+    pub replays_dir: Option<PathBuf>,
     capacity: u32,
+    settings: GameSettings,
+    next_bot_id: u64,
     pid_to_gid: HashMap<u64, u64>,
     pub players: HashMap<u64, Player>,
-    pub game: Option<Arc<Mutex<Game>>>,
+    pub game_match: Option<Arc<Mutex<Match>>>,
     pub game_thread: Option<thread::JoinHandle<()>>,
 }
 
@@ -55,9 +77,17 @@ impl Room {
      */
     pub fn new_with_capacity(capacity: u32) -> Room {
         Room {
+            name: String::new(),
+            code: String::new(),
+            password: None,
+            private: false,
+            owner: 0,
+            replays_dir: None,
             capacity,
+            settings: GameSettings::default(),
+            next_bot_id: FIRST_BOT_ID,
             players: HashMap::new(),
-            game: None,
+            game_match: None,
             game_thread: None,
             pid_to_gid: HashMap::new()
         }
@@ -72,52 +102,483 @@ impl Room {
     }
 
     /**
-     * Join the room
+     * Join the room. A number is added to the name if it is already used
      * @param id    The player id
      * @param rx    The game stream to fill
+     * @param name  The name of the player
      * @return      If the operation is successful
      */
-    pub fn join(&mut self, id: u64, rx: GameStream) -> bool {
-        if self.capacity <= self.players.len() as u32 {
+    pub fn join(&mut self, id: u64, rx: GameStream, name: String) -> bool {
+        if self.has_place().is_err() {
             return false;
         }
-        if self.game.is_some() {
-            return false;
+        if self.capacity <= self.players.len() as u32 {
+            // Humans take the place of bots
+            let bot = self.players.iter().find(|(_, p)| p.bot.is_some()).map(|(pid, _)| *pid);
+            self.players.remove(&bot.unwrap());
         }
+        let name = self.available_name(name);
         self.players.insert(id, Player {
-            rx
+            rx,
+            name,
+            team: None,
+            bot: None,
+            ready: false,
         });
         true
     }
 
     /**
-     * Leave the room
+     * @param password  The password given by the player
+     * @return          If a player can join the room, or why it can't
+     */
+    pub fn can_join(&self, password: Option<&str>) -> Result<(), JoinError> {
+        if self.password.is_some() && self.password.as_deref() != password {
+            return Err(JoinError::WrongPassword);
+        }
+        self.has_place()
+    }
+
+    /**
+     * @return If the game isn't launched and a place is free, humans can take the place of bots
+     */
+    fn has_place(&self) -> Result<(), JoinError> {
+        if self.is_running() {
+            return Err(JoinError::Running);
+        }
+        if self.capacity <= self.players.len() as u32 && self.players.values().all(|p| p.bot.is_none()) {
+            return Err(JoinError::Full);
+        }
+        Ok(())
+    }
+
+    /**
+     * @param name  The wanted name
+     * @return      The name, with a number if another player uses it. Long names are
+     *              shortened to fit the number
+     */
+    fn available_name(&self, name: String) -> String {
+        let mut candidate = name.clone();
+        let mut idx = 2;
+        while self.name_used(&candidate, None) {
+            let suffix = format!(" {}", idx);
+            let base: String = name.chars().take(MAX_NAME_LENGTH - suffix.len()).collect();
+            candidate = format!("{}{}", base.trim_end(), suffix);
+            idx += 1;
+        }
+        candidate
+    }
+
+    /**
+     * @param name      The name to check
+     * @param except    A player to ignore
+     * @return          If a player of the room uses the name, case is ignored
+     */
+    fn name_used(&self, name: &str, except: Option<u64>) -> bool {
+        self.players.iter()
+            .any(|(pid, p)| Some(*pid) != except && p.name.to_lowercase() == name.to_lowercase())
+    }
+
+    /**
+     * A player changes its name
+     * @param id    The player id
+     * @param name  The new name, already validated
+     * @return      If the operation is successful, or why it failed
+     */
+    pub fn rename_player(&mut self, id: u64, name: &str) -> Result<(), String> {
+        if !self.players.contains_key(&id) {
+            return Err(String::from("Player is not in the room"));
+        }
+        if self.name_used(name, Some(id)) {
+            return Err(format!("Name {} is already used", name));
+        }
+        self.players.get_mut(&id).unwrap().name = name.to_string();
+        Ok(())
+    }
+
+    /**
+     * Add a bot in the room
+     * @param difficulty    The difficulty of the bot
+     * @return              The id of the bot, None if the operation failed
+     */
+    pub fn add_bot(&mut self, difficulty: Difficulty) -> Option<u64> {
+        if self.is_running() {
+            warn!("Can't add a bot during a game");
+            return None;
+        }
+        if self.capacity <= self.players.len() as u32 {
+            warn!("Can't add a bot because room is full");
+            return None;
+        }
+        let id = self.next_bot_id;
+        self.next_bot_id += 1;
+        let name = self.available_name(format!("Bot {}", id - FIRST_BOT_ID + 1));
+        self.players.insert(id, Player {
+            rx: Arc::new(Mutex::new(Vec::new())),
+            name,
+            team: None,
+            bot: Some(difficulty),
+            ready: true,
+        });
+        self.inform_players();
+        self.inform_teams();
+        Some(id)
+    }
+
+    /**
+     * Remove a bot from the room
+     * @param id    The bot id
+     * @return      If the operation is successful
+     */
+    pub fn remove_bot(&mut self, id: u64) -> bool {
+        if self.is_running() {
+            warn!("Can't remove a bot during a game");
+            return false;
+        }
+        if !self.players.get(&id).is_some_and(|p| p.bot.is_some()) {
+            warn!("Can't remove bot because not found");
+            return false;
+        }
+        self.players.remove(&id);
+        self.inform_players();
+        self.inform_teams();
+        true
+    }
+
+    /**
+     * Configure teams for the next games
+     * @param teams         Number of teams, 0 for free for all
+     * @param friendly_fire If teammates' blasts are lethal
+     * @return              If the operation is successful
+     */
+    pub fn set_teams(&mut self, teams: u32, friendly_fire: bool) -> bool {
+        if self.is_running() {
+            warn!("Can't change teams during a game");
+            return false;
+        }
+        if teams == 1 || teams > self.settings.players {
+            warn!("Invalid number of teams: {}", teams);
+            return false;
+        }
+        self.settings.teams = teams;
+        self.settings.friendly_fire = friendly_fire;
+        for player in self.players.values_mut() {
+            if player.team.is_some_and(|t| t >= teams) {
+                player.team = None;
+            }
+        }
+        self.inform_teams();
+        true
+    }
+
+    /**
+     * Configure the settings of the next games
+     * @param settings  The new settings
+     * @return          If the operation is successful, or why it failed
+     */
+    pub fn set_settings(&mut self, settings: GameSettings) -> Result<(), String> {
+        if self.is_running() {
+            return Err(String::from("Can't change settings during a game"));
+        }
+        settings.validate()?;
+        if (settings.players as usize) < self.players.len() {
+            return Err(format!("Too many players in the room for {} players", settings.players));
+        }
+        let teams = settings.teams;
+        self.capacity = settings.players;
+        self.settings = settings;
+        for player in self.players.values_mut() {
+            if player.team.is_some_and(|t| t >= teams) {
+                player.team = None;
+            }
+        }
+        let buf = ServerMessage::Settings(SettingsMsg::new(self.settings.clone())).to_vec();
+        for player in self.players.values() {
+            player.rx.lock().unwrap().push(buf.clone());
+        }
+        self.inform_teams();
+        Ok(())
+    }
+
+    /**
+     * A player choose a team
      * @param id    The player id
+     * @param team  The team to join
      * @return      If the operation is successful
      */
+    pub fn choose_team(&mut self, id: u64, team: u32) -> bool {
+        if self.is_running() {
+            warn!("Can't change team during a game");
+            return false;
+        }
+        if team >= self.settings.teams {
+            warn!("Invalid team {}", team);
+            return false;
+        }
+        match self.players.get_mut(&id) {
+            Some(player) => player.team = Some(team),
+            None => {
+                warn!("Can't choose team because player is not in the room");
+                return false;
+            }
+        }
+        self.inform_teams();
+        true
+    }
+
+    /**
+     * Put players without a team in the smallest teams
+     */
+    fn balance_teams(&mut self) {
+        if self.settings.teams == 0 {
+            for player in self.players.values_mut() {
+                player.team = None;
+            }
+            return;
+        }
+        let mut team_sizes = vec![0; self.settings.teams as usize];
+        for player in self.players.values() {
+            if let Some(team) = player.team {
+                team_sizes[team as usize] += 1;
+            }
+        }
+        let mut pids: Vec<u64> = self.players.keys().cloned().collect();
+        pids.sort_unstable();
+        for pid in pids {
+            let player = self.players.get_mut(&pid).unwrap();
+            if player.team.is_none() {
+                let team = (0..self.settings.teams as usize).min_by_key(|t| team_sizes[*t]).unwrap();
+                team_sizes[team] += 1;
+                player.team = Some(team as u32);
+            }
+        }
+    }
+
+    /**
+     * @return The description of the room shown in the lobby
+     */
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            code: self.code.clone(),
+            name: self.name.clone(),
+            owner: self.players.get(&self.owner).map_or(String::new(), |p| p.name.clone()),
+            players: self.players.len() as u32,
+            capacity: self.capacity,
+            running: self.is_running(),
+            generator: self.settings.generator.clone(),
+            teams: self.settings.teams,
+            rounds_to_win: self.settings.rounds_to_win,
+        }
+    }
+
+    /**
+     * Send the members of the room to every player of the room
+     */
+    pub fn inform_players(&self) {
+        let mut players: Vec<RoomPlayer> = self.players.iter().map(|(pid, p)| RoomPlayer {
+            id: *pid,
+            name: p.name.clone(),
+            bot: p.bot.is_some(),
+            owner: *pid == self.owner,
+            ready: p.ready,
+            gid: self.pid_to_gid.get(pid).cloned(),
+        }).collect();
+        players.sort_by_key(|p| p.id);
+        let buf = ServerMessage::RoomPlayers(RoomPlayersMsg::new(players)).to_vec();
+        for player in self.players.values() {
+            player.rx.lock().unwrap().push(buf.clone());
+        }
+    }
+
+    /**
+     * Send the teams configuration to every player of the room
+     */
+    fn inform_teams(&self) {
+        let mut players = HashMap::new();
+        for (pid, player) in &self.players {
+            if let Some(team) = player.team {
+                players.insert(*pid, team);
+            }
+        }
+        let msg = RoomTeamsMsg::new(self.settings.teams, self.settings.friendly_fire, players);
+        let buf = ServerMessage::RoomTeams(msg).to_vec();
+        for player in self.players.values() {
+            player.rx.lock().unwrap().push(buf.clone());
+        }
+    }
+
+    /**
+     * Leave the room
+     * @param id    The player id
+     * @return      If the room is now empty
+     */
     pub fn remove_player(&mut self, id: u64) -> bool {
         if !self.players.contains_key(&id) {
             warn!("Can't remove player from room because not found");
             return false;
         }
         self.players.remove(&id);
-        self.players.len() == 0
-        // TODO unlink player from game
+        if let Some(gid) = self.pid_to_gid.remove(&id) {
+            if self.is_running() {
+                self.game_match.as_ref().unwrap().lock().unwrap().remove_player(gid);
+            }
+        }
+        // A room with only bots is empty
+        self.players.values().all(|p| p.bot.is_some())
+    }
+
+    /**
+     * Give the room to another member, connected players first
+     * @param away  Players which are disconnected
+     * @return      If the owner changed
+     */
+    pub fn migrate_owner(&mut self, away: &[u64]) -> bool {
+        let owner = self.owner;
+        let candidate = self.players.iter()
+            .filter(|(pid, p)| **pid != owner && p.bot.is_none())
+            .map(|(pid, _)| *pid)
+            .min_by_key(|pid| (away.contains(pid), *pid));
+        match candidate {
+            Some(candidate) => {
+                self.owner = candidate;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /**
+     * Stop the game of the room before removing it
+     */
+    pub fn close(&mut self) {
+        if let Some(game_match) = &self.game_match {
+            game_match.lock().unwrap().stop();
+        }
+        if let Some(game_thread) = self.game_thread.take() {
+            let _ = game_thread.join();
+        }
+    }
+
+    /**
+     * A player comes back with a new id, keep its place in the room and the game
+     * @param old_id    The previous player id
+     * @param new_id    The new player id
+     * @return          If the operation is successful
+     */
+    pub fn rebind_player(&mut self, old_id: u64, new_id: u64) -> bool {
+        let player = match self.players.remove(&old_id) {
+            Some(player) => player,
+            None => {
+                warn!("Can't rebind player because not found");
+                return false;
+            }
+        };
+        self.players.insert(new_id, player);
+        if self.owner == old_id {
+            self.owner = new_id;
+        }
+        if let Some(gid) = self.pid_to_gid.remove(&old_id) {
+            self.pid_to_gid.insert(new_id, gid);
+        }
+        true
+    }
+
+    /**
+     * Send the identity of a player coming back during a game and a snapshot
+     * of the map. Missed events are replaced by the snapshot
+     * @param id    The player id
+     */
+    pub fn send_snapshot(&self, id: u64) {
+        let (game_match, player) = match (&self.game_match, self.players.get(&id)) {
+            (Some(game_match), Some(player)) => (game_match, player),
+            _ => return,
+        };
+        // The game can't push events while the snapshot is sent
+        let game_match = game_match.lock().unwrap();
+        let mut rx = player.rx.lock().unwrap();
+        rx.clear();
+        if let Some(gid) = self.pid_to_gid.get(&id) {
+            let identity = ServerMessage::PlayerIdentity(PlayerIdentity {
+                id: *gid,
+                name: player.name.clone(),
+                team: player.team,
+            });
+            rx.push(identity.to_vec());
+        }
+        rx.push(ServerMessage::Map(MapMsg::new(game_match.game.map.clone())).to_vec());
+    }
+
+    /**
+     * A player is ready or not to play. The game is launched when everybody is ready
+     * @param id    The player id
+     * @param ready If the player is ready
+     * @return      If the operation is successful
+     */
+    pub fn set_ready(&mut self, id: u64, ready: bool) -> bool {
+        if self.is_running() {
+            warn!("Can't change ready state during a game");
+            return false;
+        }
+        match self.players.get_mut(&id) {
+            Some(player) => player.ready = ready,
+            None => {
+                warn!("Can't change ready state because player is not in the room");
+                return false;
+            }
+        }
+        self.inform_players();
+        if self.all_ready() {
+            info!("Everybody is ready, launch the game");
+            self.start_match();
+        }
+        true
+    }
+
+    /**
+     * @return if enough players are in the room and all of them are ready
+     */
+    fn all_ready(&self) -> bool {
+        let enough = self.players.len() as u32 >= MIN_PLAYERS || self.settings.fill_bots.is_some();
+        enough && self.players.values().all(|p| p.ready)
     }
 
     /**
-     * Launch the game
+     * The owner launches the game, even if some players aren't ready
      * @param id    The player id who launch the game
      * @return      If the operation is successful
      */
     pub fn launch_game(&mut self, id: u64) -> bool {
-        if self.game.is_some() && !self.game.as_ref().unwrap().lock().unwrap().finished() {
+        if id != self.owner {
+            warn!("Only the owner of the room can launch the game");
+            return false;
+        }
+        self.start_match()
+    }
+
+    /**
+     * Create the match and its thread. The match starts with a countdown
+     * @return      If the operation is successful
+     */
+    fn start_match(&mut self) -> bool {
+        if self.is_running() {
             warn!("Game already launched");
             return false;
         }
-        let game = Arc::new(Mutex::new(Game::new()));
+        if let Some(difficulty) = self.settings.fill_bots {
+            while (self.players.len() as u32) < self.capacity {
+                self.add_bot(difficulty);
+            }
+        }
+        self.balance_teams();
+        // Only spawn the players of the room
+        let mut settings = self.settings.clone();
+        settings.players = (self.players.len() as u32).max(MIN_PLAYERS).min(settings.players);
+        let mut game_match = Match::new(DEFAULT_TICK_RATE, settings);
+        game_match.set_replays_dir(self.replays_dir.clone());
+        let game_match = Arc::new(Mutex::new(game_match));
+        self.pid_to_gid.clear();
         for (pid, player) in &mut self.players {
-            let gid = game.lock().unwrap().link_player(player.clone());
+            let gid = game_match.lock().unwrap().link_player(player.clone());
             if gid.is_none() {
                 continue;
             }
@@ -126,23 +587,43 @@ impl Room {
                 *pid, gid
             );
         }
-        let game_cloned = game.clone();
-        self.game = Some(game);
+        // Players have to be ready again for the next match
+        for player in self.players.values_mut() {
+            player.ready = player.bot.is_some();
+        }
+        // Players can now find who is who in the game
+        self.inform_players();
+        // Started before the thread, so the room can stop it at any time
+        game_match.lock().unwrap().start();
+        let match_cloned = game_match.clone();
+        self.game_match = Some(game_match);
         self.game_thread = Some(thread::spawn(move || {
-            game_cloned.lock().unwrap().start();
+            let tick_rate = match_cloned.lock().unwrap().tick_rate();
+            let mut scheduler = TickScheduler::new(tick_rate);
             loop {
-                game_cloned.lock().unwrap().event_loop();
-                if game_cloned.lock().unwrap().finished() {
-                    info!("Game is finished");
+                for _ in 0..scheduler.wait() {
+                    match_cloned.lock().unwrap().event_loop();
+                }
+                if match_cloned.lock().unwrap().finished() {
+                    info!("Match is finished");
                     break;
                 }
-                thread::sleep(Duration::from_nanos(1));
             }
         }));
 
         true
     }
 
+    /**
+     * @return if a match is currently played in the room
+     */
+    pub fn is_running(&self) -> bool {
+        match &self.game_match {
+            Some(game_match) => !game_match.lock().unwrap().finished(),
+            None => false,
+        }
+    }
+
     fn player_id_to_game_id(&self, pid: u64) -> Option<u64> {
         if !self.pid_to_gid.contains_key(&pid) {
             warn!("No player found for pid {}", pid);
@@ -157,7 +638,7 @@ impl Room {
      * @return      If the operation is successful
      */
     pub fn put_bomb(&mut self, id: u64) -> bool {
-        if self.game.is_none() {
+        if self.game_match.is_none() {
             warn!("No game launched, so cannot put bomb");
             return false;
         }
@@ -165,7 +646,7 @@ impl Room {
         if gid.is_none() {
             return false;
         }
-        self.game.as_ref().unwrap().lock().unwrap().push_action(
+        self.game_match.as_ref().unwrap().lock().unwrap().push_action(
             Action::PutBomb, gid.unwrap()
         );
 
@@ -179,25 +660,43 @@ impl Room {
      * @return          If the operation is successful
      */
     pub fn move_player(&mut self, id: u64, direction: Direction) -> bool {
-        if self.game.is_none() {
+        if self.game_match.is_none() {
             warn!("No game launched, so cannot put bomb");
             return false;
         }
-        if self.game.as_ref().unwrap().lock().unwrap().finished() {
+        if self.game_match.as_ref().unwrap().lock().unwrap().finished() {
             return false;
         }
         let gid = self.player_id_to_game_id(id);
         if gid.is_none() {
             return false;
         }
-        self.game.as_ref().unwrap().lock().unwrap().push_action(
+        self.game_match.as_ref().unwrap().lock().unwrap().push_action(
             Action::Move(direction), gid.unwrap()
         );
 
         true
     }
 
-    pub fn get_map_msg(&self) -> MapMsg {
-        MapMsg::new(self.game.as_ref().unwrap().lock().unwrap().map.clone())
+    /**
+     * A player punch a bomb
+     * @param id        The player id
+     * @param direction The direction of the punch
+     * @return          If the operation is successful
+     */
+    pub fn punch_bomb(&mut self, id: u64, direction: Direction) -> bool {
+        if self.game_match.is_none() {
+            warn!("No game launched, so cannot punch bomb");
+            return false;
+        }
+        let gid = self.player_id_to_game_id(id);
+        if gid.is_none() {
+            return false;
+        }
+        self.game_match.as_ref().unwrap().lock().unwrap().push_action(
+            Action::Punch(direction), gid.unwrap()
+        );
+
+        true
     }
 }
\ No newline at end of file
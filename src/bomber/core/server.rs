@@ -25,17 +25,25 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 use super::super::gen::utils::Direction;
-use super::Room;
-use crate::serde::Serialize;
-
-use rmps::Serializer;
-use std::collections::HashMap;
+use super::{validate_name, Room, RoomOptions};
+use super::game::{Difficulty, GameSettings};
+use crate::bomber::net::diff_msg::SerializedEvent;
+use crate::bomber::net::msg::{JoinError, KickedMsg, RoomClosedMsg, RoomInfo, ServerMessage, SessionMsg};
+
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+// Time to reconnect before a disconnected player is removed
+pub const RECONNECT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+// Invite codes, without characters which look alike
+const ROOM_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ROOM_CODE_LENGTH: usize = 8;
 
-pub type PlayerStream = Arc<Mutex<Option<Vec<u8>>>>;
 pub type GameStream = Arc<Mutex<Vec<Vec<u8>>>>;
 struct Stream {
-    pub tx: PlayerStream,
     pub rx: GameStream
 }
 
@@ -47,7 +55,12 @@ pub struct Server {
     rooms: HashMap<u64, Room>,
     player_to_room: HashMap<u64, u64>,
     current_room_id: u64,
-    player_to_stream: HashMap<u64, Stream>
+    player_to_stream: HashMap<u64, Stream>,
+    player_names: HashMap<u64, String>,
+    sessions: HashMap<String, u64>,
+    disconnected: HashMap<u64, Instant>,
+    running_rooms: HashSet<u64>,
+    replays_dir: Option<PathBuf>,
 }
 
 impl Server {
@@ -61,84 +74,429 @@ impl Server {
             player_to_room: HashMap::new(),
             current_room_id: 0,
             player_to_stream: HashMap::new(),
+            player_names: HashMap::new(),
+            sessions: HashMap::new(),
+            disconnected: HashMap::new(),
+            running_rooms: HashSet::new(),
+            replays_dir: None,
         }
     }
 
+    /**
+     * Save the replays of the games played in the next rooms
+     * @param dir   The directory, None to not save replays
+     */
+    pub fn set_replays_dir(&mut self, dir: Option<PathBuf>) {
+        self.replays_dir = dir;
+    }
+
     /**
      * A new player is coming. Add it to the lobby
      * @param id    The player id
      * @return      If the operation is successful
      */
-    pub fn join_server(&mut self, id: u64, player_stream: PlayerStream) -> bool {
+    pub fn join_server(&mut self, id: u64) -> bool {
         info!("Client ({}) is in the lobby", id);
         let rx = Arc::new(Mutex::new(Vec::new()));
+        // Give a token to the player, so it can come back if the connection drops
+        let mut rng = rand::thread_rng();
+        let token = format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>());
+        let msg = ServerMessage::Session(SessionMsg::new(id, token.clone()));
+        rx.lock().unwrap().push(msg.to_vec());
+        self.sessions.insert(token, id);
         self.player_to_room.insert(id, 0);
         self.player_to_stream.insert(id, Stream {
-            tx: player_stream,
             rx: rx.clone(),
         });
-        self.lobby.join(id, rx)
+        let name = format!("Player {}", id);
+        self.player_names.insert(id, name.clone());
+        self.lobby.join(id, rx, name)
     }
 
     /**
-     * A player is creating a room. Add it to this room at the end
+     * A player chooses its display name, unique in its room
      * @param id    The player id
-     * @return      The id of the room created
+     * @param name  The name
+     * @return      If the operation is successful, or why it failed
      */
-    pub fn create_room(&mut self, id: u64) -> u64 {
-        if !self.player_to_room.contains_key(&id) {
-            warn!("Can't create room because player is not in the server");
-            return 0;
+    pub fn set_name(&mut self, id: u64, name: &str) -> Result<(), String> {
+        let name = validate_name(name)?;
+        let room_id = match self.player_to_room.get(&id) {
+            Some(room_id) => *room_id,
+            None => return Err(String::from("Player is not in the server")),
+        };
+        if room_id == 0 {
+            self.lobby.rename_player(id, &name)?;
+        } else {
+            match self.rooms.get_mut(&room_id) {
+                Some(room) => room.rename_player(id, &name)?,
+                None => return Err(String::from("Room doesn't exists")),
+            }
+            self.rooms[&room_id].inform_players();
+            self.room_updated(room_id);
         }
+        info!("Client ({}) is now named {}", id, name);
+        self.player_names.insert(id, name);
+        Ok(())
+    }
 
+    /**
+     * The connection of a player dropped. Its place is kept during the grace period
+     * @param id    The player id
+     * @return      If the operation is successful
+     */
+    pub fn disconnect(&mut self, id: u64) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            return false;
+        }
+        info!("Client ({}) disconnected", id);
+        self.disconnected.insert(id, Instant::now());
+        // Connected players can't wait for the owner to configure the room
         let room_id = self.player_to_room[&id];
+        if self.rooms.get(&room_id).is_some_and(|room| room.owner == id) {
+            self.migrate_owner(room_id);
+        }
+        true
+    }
 
+    /**
+     * A new connection resumes the session of a disconnected player. The player
+     * gets back its room and game, then receives a snapshot of the map
+     * @param id    The new player id
+     * @param token The session token of the previous connection
+     * @return      The room of the player, None if the session is unknown or still connected
+     */
+    pub fn resume(&mut self, id: u64, token: &String) -> Option<u64> {
+        let old_id = match self.sessions.get(token) {
+            Some(old_id) => *old_id,
+            None => {
+                warn!("Client ({}) try to resume an unknown session", id);
+                return None;
+            }
+        };
+        if old_id == id || !self.player_to_stream.contains_key(&id) {
+            return None;
+        }
+        if !self.disconnected.contains_key(&old_id) {
+            warn!("Client ({}) try to resume the session of connected client ({})", id, old_id);
+            return None;
+        }
+        let room_id = self.player_to_room[&old_id];
         if room_id != 0 && !self.rooms.contains_key(&room_id) {
-            warn!("Can't remove player from Room because rooms doesn't exists");
-            return 0;
+            warn!("Can't resume session because room doesn't exists");
+            return None;
+        }
+
+        // Drop the session of the new connection
+        self.sessions.retain(|_, pid| *pid != id);
+        let current_room = self.player_to_room[&id];
+        self.leave_current_room(id, current_room);
+        self.sessions.insert(token.clone(), id);
+        self.disconnected.remove(&old_id);
+        self.player_to_room.remove(&old_id);
+        self.player_to_room.insert(id, room_id);
+        let old_stream = self.player_to_stream.remove(&old_id).unwrap();
+        self.player_to_stream.get_mut(&id).unwrap().rx = old_stream.rx.clone();
+
+        let room = if room_id == 0 { &mut self.lobby } else { self.rooms.get_mut(&room_id).unwrap() };
+        room.rebind_player(old_id, id);
+        room.send_snapshot(id);
+        if room_id != 0 {
+            room.inform_players();
+        }
+        let name = self.player_names.remove(&old_id).unwrap_or_default();
+        self.player_names.insert(id, name);
+        info!("Client ({}) resumed session of ({}) in Room ({})", id, old_id, room_id);
+        Some(room_id)
+    }
+
+    /**
+     * Remove players which didn't come back during the grace period
+     */
+    pub fn remove_expired_sessions(&mut self) {
+        let expired: Vec<u64> = self.disconnected.iter()
+            .filter(|(_, at)| at.elapsed() >= RECONNECT_GRACE_PERIOD)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.remove_player(id);
+        }
+    }
+
+    /**
+     * Matches end in their own thread, tell the lobby which rooms are available again
+     */
+    pub fn update_finished_matches(&mut self) {
+        let finished: Vec<u64> = self.running_rooms.iter()
+            .filter(|room_id| !self.rooms.get(room_id).is_some_and(|room| room.is_running()))
+            .cloned()
+            .collect();
+        for room_id in finished {
+            self.running_rooms.remove(&room_id);
+            self.room_updated(room_id);
+        }
+    }
+
+    /**
+     * Remove a player from the server
+     * @param id    The player id
+     */
+    fn remove_player(&mut self, id: u64) {
+        info!("Remove client ({})", id);
+        self.disconnected.remove(&id);
+        self.sessions.retain(|_, pid| *pid != id);
+        self.player_to_stream.remove(&id);
+        self.player_names.remove(&id);
+        if let Some(room_id) = self.player_to_room.remove(&id) {
+            self.leave_current_room(id, room_id);
         }
+    }
 
+    /**
+     * Remove a player from its room, the room is removed if nobody is left
+     * @param id        The player id
+     * @param room_id   The room of the player
+     */
+    fn leave_current_room(&mut self, id: u64, room_id: u64) {
         if room_id == 0 {
             self.lobby.remove_player(id);
+            return;
+        }
+        let empty = match self.rooms.get_mut(&room_id) {
+            Some(room) => room.remove_player(id),
+            None => return,
+        };
+        if empty {
+            self.close_room(room_id);
+        } else if self.rooms[&room_id].owner == id {
+            self.migrate_owner(room_id);
         } else {
-            let remove = self.rooms.get_mut(&room_id).unwrap().remove_player(id);
-            if remove {
-                info!("Remove room ({})", room_id);
-                self.rooms.remove(&room_id);
+            self.rooms[&room_id].inform_players();
+            self.room_updated(room_id);
+        }
+    }
+
+    /**
+     * The owner of a room leaves or is disconnected, give the room to another member
+     * @param room_id   The room to migrate
+     */
+    fn migrate_owner(&mut self, room_id: u64) {
+        let away: Vec<u64> = self.disconnected.keys().cloned().collect();
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if room.migrate_owner(&away) {
+            info!("Client ({}) now owns Room ({})", room.owner, room_id);
+        }
+        room.inform_players();
+        self.room_updated(room_id);
+    }
+
+    /**
+     * Move a player to the lobby
+     * @param id    The player id, removed from its room
+     */
+    fn join_lobby(&mut self, id: u64) {
+        let rx = self.player_to_stream[&id].rx.clone();
+        self.lobby.join(id, rx, self.player_names[&id].clone());
+        *self.player_to_room.get_mut(&id).unwrap() = 0;
+        info!("Client ({}) is now in Room ({})", id, 0);
+    }
+
+    /**
+     * Remove a room and stop its game
+     * @param room_id   The room to remove
+     */
+    fn close_room(&mut self, room_id: u64) {
+        self.running_rooms.remove(&room_id);
+        if let Some(mut room) = self.rooms.remove(&room_id) {
+            info!("Remove room ({})", room_id);
+            room.close();
+            if !room.private {
+                self.notify_lobby(ServerMessage::RoomClosed(RoomClosedMsg::new(room.code)));
             }
         }
+    }
+
+    /**
+     * @return The public rooms of the server, oldest first
+     */
+    pub fn list_rooms(&self) -> Vec<RoomInfo> {
+        let mut ids: Vec<&u64> = self.rooms.keys().collect();
+        ids.sort_unstable();
+        ids.into_iter()
+            .map(|id| &self.rooms[id])
+            .filter(|room| !room.private)
+            .map(|room| room.info())
+            .collect()
+    }
 
-        let mut room = Room::new_with_capacity(4);
+    /**
+     * @param room_id   The room id
+     * @return          The invite code of the room, None for the lobby
+     */
+    pub fn room_code(&self, room_id: u64) -> Option<String> {
+        self.rooms.get(&room_id).map(|room| room.code.clone())
+    }
+
+    /**
+     * @return A new invite code, used by no room
+     */
+    fn new_room_code(&self) -> String {
+        let mut rng = rand::thread_rng();
+        loop {
+            let code: String = (0..ROOM_CODE_LENGTH)
+                .map(|_| ROOM_CODE_CHARS[rng.gen_range(0, ROOM_CODE_CHARS.len())] as char)
+                .collect();
+            if self.rooms.values().all(|room| room.code != code) {
+                return code;
+            }
+        }
+    }
+
+    /**
+     * Send a message to every player in the lobby
+     * @param msg   The message to send
+     */
+    fn notify_lobby(&self, msg: ServerMessage) {
+        let buf = msg.to_vec();
+        for player in self.lobby.players.values() {
+            player.rx.lock().unwrap().push(buf.clone());
+        }
+    }
+
+    /**
+     * Inform the lobby that the description of a room changed
+     * @param room_id   The room which changed
+     */
+    fn room_updated(&self, room_id: u64) {
+        match self.rooms.get(&room_id) {
+            Some(room) if !room.private => self.notify_lobby(ServerMessage::RoomUpdated(room.info())),
+            _ => {},
+        }
+    }
+
+    /**
+     * A player is creating a room. Add it to this room at the end
+     * @param id        The player id
+     * @param options   The name, password and visibility of the room
+     * @return          The id of the room created
+     */
+    pub fn create_room(&mut self, id: u64, options: RoomOptions) -> u64 {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't create room because player is not in the server");
+            return 0;
+        }
+
+        let name = match options.name.map(|name| validate_name(&name)) {
+            Some(Ok(name)) => name,
+            Some(Err(reason)) => {
+                warn!("Can't create room: {}", reason);
+                return 0;
+            },
+            None => format!("{}'s room", self.player_names[&id]),
+        };
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id != 0 && !self.rooms.contains_key(&room_id) {
+            warn!("Can't remove player from Room because rooms doesn't exists");
+            return 0;
+        }
+
+        self.leave_current_room(id, room_id);
+
+        let mut room = Room::new_with_capacity(GameSettings::default().players);
+        room.name = name;
+        room.code = self.new_room_code();
+        room.password = options.password.filter(|password| !password.is_empty());
+        room.private = options.private;
+        room.owner = id;
+        room.replays_dir = self.replays_dir.clone();
         let rx = self.player_to_stream[&id].rx.clone();
-        if room.join(id, rx) {
+        let name = self.player_names[&id].clone();
+        if room.join(id, rx.clone(), name.clone()) {
+            room.inform_players();
             self.current_room_id += 1;
+            if !room.private {
+                self.notify_lobby(ServerMessage::RoomCreated(room.info()));
+            }
             self.rooms.insert(self.current_room_id, room);
             *self.player_to_room.get_mut(&id).unwrap() = self.current_room_id;
             info!("Client ({}) is now in Room ({})", id, self.current_room_id);
         } else {
+            self.lobby.join(id, rx, name);
             *self.player_to_room.get_mut(&id).unwrap() = 0;
             warn!("Client ({}) can't join room. Going to room ({})", id, 0);
+            return 0;
         }
 
         self.current_room_id
     }
 
     /**
-     * A player is joining an existing room.
+     * A player is joining an existing room with its invite code
      * @param id        The player id
-     * @param join_id   The room to join
-     * @return          If the operation is successful
+     * @param code      The invite code of the room
+     * @param password  The password of the room, if any
+     * @return          If the operation is successful, or why it failed
      */
-    pub fn join_room(&mut self, id: u64, join_id: u64) -> bool {
+    pub fn join_room(&mut self, id: u64, code: &str, password: Option<&str>) -> Result<(), JoinError> {
         if !self.player_to_room.contains_key(&id) {
             warn!("Can't join room because player is not in the server");
-            return false;
+            return Err(JoinError::NotFound);
         }
 
         let room_id = self.player_to_room[&id];
 
+        if room_id != 0 && !self.rooms.contains_key(&room_id) {
+            warn!("Can't remove player from Room because rooms doesn't exists");
+            return Err(JoinError::NotFound);
+        }
+
+        let join_id = match self.rooms.iter().find(|(_, room)| room.code == code) {
+            Some((join_id, _)) => *join_id,
+            None => {
+                warn!("Player try to join inexistant room {}", code);
+                return Err(JoinError::NotFound);
+            }
+        };
+
         if room_id == join_id {
             warn!("Player try to join its current room");
+            return Err(JoinError::AlreadyInRoom);
+        }
+
+        // Players stay in their room if they can't join
+        if let Err(reason) = self.rooms[&join_id].can_join(password) {
+            warn!("Client ({}) can't join Room ({}): {:?}", id, join_id, reason);
+            return Err(reason);
+        }
+
+        self.leave_current_room(id, room_id);
+
+        let rx = self.player_to_stream[&id].rx.clone();
+        let name = self.player_names[&id].clone();
+        let room = self.rooms.get_mut(&join_id).unwrap();
+        room.join(id, rx, name);
+        room.inform_players();
+        *self.player_to_room.get_mut(&id).unwrap() = join_id;
+        self.room_updated(join_id);
+        info!("Client ({}) is now in Room ({})", id, join_id);
+        Ok(())
+    }
+
+    pub fn leave_room(&mut self, id: u64) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't leave room because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Player try to leave lobby");
             return false;
         }
 
@@ -147,68 +505,250 @@ impl Server {
             return false;
         }
 
-        if !self.rooms.contains_key(&join_id) {
-            warn!("Player try to join inexistant room {}", join_id);
+        self.leave_current_room(id, room_id);
+        self.join_lobby(id);
+        true
+    }
+
+    /**
+     * The owner of a room sends a member back to the lobby
+     * @param id        The player id
+     * @param kicked    The player to kick
+     * @return          If the operation is successful
+     */
+    pub fn kick_player(&mut self, id: u64, kicked: u64) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't kick because player is not in the server");
             return false;
         }
 
+        let room_id = self.player_to_room[&id];
+
         if room_id == 0 {
-            self.lobby.remove_player(id);
-        } else {
-            let remove = self.rooms.get_mut(&room_id).unwrap().remove_player(id);
-            if remove {
-                info!("Remove room ({})", room_id);
-                self.rooms.remove(&room_id);
-            }
+            warn!("Can't kick from lobby");
+            return false;
         }
 
-        let rx = self.player_to_stream[&id].rx.clone();
-        if join_id == 0 {
-            self.lobby.join(id, rx);
-            *self.player_to_room.get_mut(&id).unwrap() = join_id;
-        } else {
-            if self.rooms.get_mut(&join_id).unwrap().join(id, rx) {
-                *self.player_to_room.get_mut(&id).unwrap() = join_id;
-                info!("Client ({}) is now in Room ({})", id, join_id);
-            } else {
-                *self.player_to_room.get_mut(&id).unwrap() = 0;
-                warn!("Client ({}) can't join room. Going to room ({})", id, 0);
-            }
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't kick because room doesn't exists");
+            return false;
+        }
+
+        if self.rooms[&room_id].owner != id {
+            warn!("Can't kick because player doesn't own the room");
+            return false;
         }
 
+        if kicked == id || self.player_to_room.get(&kicked) != Some(&room_id) {
+            warn!("Can't kick player ({}) because not in the room", kicked);
+            return false;
+        }
+
+        self.leave_current_room(kicked, room_id);
+        let msg = ServerMessage::Kicked(KickedMsg::new(self.rooms[&room_id].code.clone()));
+        self.player_to_stream[&kicked].rx.lock().unwrap().push(msg.to_vec());
+        self.join_lobby(kicked);
+        info!("Client ({}) kicked ({}) from Room ({})", id, kicked, room_id);
         true
     }
 
-    pub fn leave_room(&mut self, id: u64) -> bool {
+    /**
+     * A player configures the teams of its room
+     * @param id            The player id
+     * @param teams         Number of teams, 0 for free for all
+     * @param friendly_fire If teammates' blasts are lethal
+     * @return              If the operation is successful
+     */
+    pub fn set_teams(&mut self, id: u64, teams: u32, friendly_fire: bool) -> bool {
         if !self.player_to_room.contains_key(&id) {
-            warn!("Can't leave room because player is not in the server");
+            warn!("Can't set teams because player is not in the server");
             return false;
         }
 
         let room_id = self.player_to_room[&id];
 
         if room_id == 0 {
-            warn!("Player try to leave lobby");
+            warn!("Can't set teams in lobby");
             return false;
         }
 
-        if room_id != 0 && !self.rooms.contains_key(&room_id) {
-            warn!("Can't remove player from Room because rooms doesn't exists");
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't set teams because room doesn't exists");
             return false;
         }
 
-        if room_id != 0 {
-            let remove = self.rooms.get_mut(&room_id).unwrap().remove_player(id);
-            if remove {
-                info!("Remove room ({})", room_id);
-                self.rooms.remove(&room_id);
-            }
+        if self.rooms[&room_id].owner != id {
+            warn!("Can't set teams because player doesn't own the room");
+            return false;
         }
 
-        let rx = self.player_to_stream[&id].rx.clone();
-        self.lobby.join(id, rx);
-        *self.player_to_room.get_mut(&id).unwrap() = 0;
-        info!("Client ({}) is now in Room ({})", id, 0);
+        if !self.rooms.get_mut(&room_id).unwrap().set_teams(teams, friendly_fire) {
+            return false;
+        }
+        self.room_updated(room_id);
+        true
+    }
+
+    /**
+     * A player adds a bot in its room
+     * @param id            The player id
+     * @param difficulty    The difficulty of the bot
+     * @return              If the operation is successful
+     */
+    pub fn add_bot(&mut self, id: u64, difficulty: Difficulty) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't add bot because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Can't add bot in lobby");
+            return false;
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't add bot because room doesn't exists");
+            return false;
+        }
+
+        if self.rooms[&room_id].owner != id {
+            warn!("Can't add bot because player doesn't own the room");
+            return false;
+        }
+
+        if self.rooms.get_mut(&room_id).unwrap().add_bot(difficulty).is_none() {
+            return false;
+        }
+        self.room_updated(room_id);
+        true
+    }
+
+    /**
+     * A player removes a bot from its room
+     * @param id        The player id
+     * @param bot_id    The bot to remove
+     * @return          If the operation is successful
+     */
+    pub fn remove_bot(&mut self, id: u64, bot_id: u64) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't remove bot because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Can't remove bot in lobby");
+            return false;
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't remove bot because room doesn't exists");
+            return false;
+        }
+
+        if self.rooms[&room_id].owner != id {
+            warn!("Can't remove bot because player doesn't own the room");
+            return false;
+        }
+
+        if !self.rooms.get_mut(&room_id).unwrap().remove_bot(bot_id) {
+            return false;
+        }
+        self.room_updated(room_id);
+        true
+    }
+
+    /**
+     * A player configures the settings of its room
+     * @param id        The player id
+     * @param settings  The new settings
+     * @return          If the operation is successful, or why it failed
+     */
+    pub fn set_settings(&mut self, id: u64, settings: GameSettings) -> Result<(), String> {
+        let room_id = match self.player_to_room.get(&id) {
+            Some(room_id) => *room_id,
+            None => return Err(String::from("Player is not in the server")),
+        };
+
+        if room_id == 0 {
+            return Err(String::from("Can't set settings in lobby"));
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            return Err(String::from("Room doesn't exists"));
+        }
+
+        if self.rooms[&room_id].owner != id {
+            return Err(String::from("Only the owner of the room can change the settings"));
+        }
+
+        if let Err(reason) = self.rooms.get_mut(&room_id).unwrap().set_settings(settings) {
+            warn!("Client ({}) can't set settings: {}", id, reason);
+            return Err(reason);
+        }
+        self.room_updated(room_id);
+        Ok(())
+    }
+
+    /**
+     * A player chooses its team
+     * @param id        The player id
+     * @param team      The team to join
+     * @return          If the operation is successful
+     */
+    pub fn choose_team(&mut self, id: u64, team: u32) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't choose team because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Can't choose team in lobby");
+            return false;
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't choose team because room doesn't exists");
+            return false;
+        }
+
+        self.rooms.get_mut(&room_id).unwrap().choose_team(id, team)
+    }
+
+    /**
+     * A player is ready or not to play
+     * @param id        The player id
+     * @param ready     If the player is ready
+     * @return          If the operation is successful
+     */
+    pub fn set_ready(&mut self, id: u64, ready: bool) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't set ready because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Can't set ready in lobby");
+            return false;
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't set ready because room doesn't exists");
+            return false;
+        }
+
+        if !self.rooms.get_mut(&room_id).unwrap().set_ready(id, ready) {
+            return false;
+        }
+        // The game may be launched
+        self.room_updated(room_id);
         true
     }
 
@@ -235,11 +775,14 @@ impl Server {
             return false;
         }
 
-        self.rooms.get_mut(&room_id).unwrap().launch_game(id);
+        if !self.rooms.get_mut(&room_id).unwrap().launch_game(id) {
+            return false;
+        }
 
         info!("Client ({}) launched game in room ({})", id, self.current_room_id);
 
-        self.send_resources(room_id);
+        self.running_rooms.insert(room_id);
+        self.room_updated(room_id);
 
         true
     }
@@ -305,6 +848,33 @@ impl Server {
         true
     }
 
+    /**
+     * A player punch a bomb
+     * @param id        The player id
+     * @param direction The direction of the punch
+     * @return          If the operation is successful
+     */
+    pub fn punch_bomb(&mut self, id: u64, direction: Direction) -> bool {
+        if !self.player_to_room.contains_key(&id) {
+            warn!("Can't punch because player is not in the server");
+            return false;
+        }
+
+        let room_id = self.player_to_room[&id];
+
+        if room_id == 0 {
+            warn!("Can't punch from lobby");
+            return false;
+        }
+
+        if !self.rooms.contains_key(&room_id) {
+            warn!("Can't punch because room doesn't exists");
+            return false;
+        }
+
+        self.rooms.get_mut(&room_id).unwrap().punch_bomb(id, direction)
+    }
+
     pub fn get_events(&mut self, player: &u64) -> Vec<Vec<u8>> {
         if self.player_to_stream.contains_key(player) {
             let mut rx = self.player_to_stream[player].rx.lock().unwrap();
@@ -314,25 +884,4 @@ impl Server {
         }
         Vec::new()
     }
-
-    fn send_resources(&mut self, room_id: u64) {
-        info!("Sending resources for room {}", room_id);
-        let players = self.rooms.get(&room_id).unwrap().players.keys();
-        let mut buf = Vec::new();
-        let msg = self.rooms.get(&room_id).unwrap().get_map_msg();
-        msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        let len = buf.len() as u16;
-        let mut send_buf : Vec<u8> = Vec::with_capacity(65536);
-        send_buf.push((len >> 8) as u8);
-        send_buf.push((len as u16 % (2 as u16).pow(8)) as u8);
-        send_buf.append(&mut buf);
-
-        for player in players {
-            if self.player_to_stream.contains_key(player) {
-                // TODO is it quick enough? Or add queue
-                info!("Sending resources for player {}", player);
-                *self.player_to_stream[player].tx.lock().unwrap() = Some(send_buf.clone());
-            }
-        }
-    }
 }
\ No newline at end of file
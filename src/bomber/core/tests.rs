@@ -0,0 +1,454 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rmps::Deserializer;
+use serde::Deserialize;
+use std::io::Cursor;
+
+use super::{Player, RoomOptions, Server};
+use super::game::{Action, Difficulty, GameSettings, Match, DEFAULT_TICK_RATE};
+use crate::bomber::gen::utils::Direction;
+use crate::bomber::net::diff_msg::Countdown;
+use crate::bomber::net::msg::{JoinError, KickedMsg, ServerMessage};
+use std::sync::{Arc, Mutex};
+
+fn decode<'a, T: Deserialize<'a>>(pkt: &'a Vec<u8>) -> T {
+    Deserialize::deserialize(&mut Deserializer::new(Cursor::new(&pkt[..]))).unwrap()
+}
+
+fn decode_all(events: &Vec<Vec<u8>>) -> Vec<ServerMessage> {
+    events.iter().map(|pkt| decode(pkt)).collect()
+}
+
+/**
+ * Connect a client and get its session token
+ */
+fn connect(server: &mut Server, id: u64) -> String {
+    server.join_server(id);
+    match decode(&server.get_events(&id)[0]) {
+        ServerMessage::Session(session) => {
+            assert_eq!(session.id, id);
+            session.token
+        },
+        msg => panic!("Unexpected message {:?}", msg),
+    }
+}
+
+/**
+ * Create a public room and get its invite code
+ */
+fn create_room(server: &mut Server, id: u64) -> String {
+    let room = server.create_room(id, RoomOptions::default());
+    server.room_code(room).unwrap()
+}
+
+#[test]
+fn players_resume_their_game() {
+    let mut server = Server::new();
+    let token = connect(&mut server, 0);
+    let room = server.create_room(0, RoomOptions::default());
+    assert!(server.add_bot(0, Difficulty::Easy));
+    assert!(server.launch_game(0));
+    server.get_events(&0);
+
+    let new_token = connect(&mut server, 1);
+    assert_ne!(token, new_token);
+    // Connected sessions can't be taken over
+    assert_eq!(server.resume(1, &token), None);
+    assert!(server.disconnect(0));
+    assert_eq!(server.resume(1, &String::from("unknown")), None);
+    assert_eq!(server.resume(1, &token), Some(room));
+
+    // The game goes on with the new connection
+    let msgs = decode_all(&server.get_events(&1));
+    match &msgs[..2] {
+        [ServerMessage::PlayerIdentity(_), ServerMessage::Map(_)] => {},
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    assert!(server.move_player(1, Direction::East));
+    assert!(server.get_events(&0).is_empty());
+    // The token stays valid for the next disconnection, not the one of the new connection
+    assert_eq!(server.resume(2, &new_token), None);
+}
+
+#[test]
+fn resumed_players_leave_their_new_room() {
+    let mut server = Server::new();
+    let token = connect(&mut server, 0);
+    create_room(&mut server, 0);
+    connect(&mut server, 1);
+    connect(&mut server, 2);
+    let room = create_room(&mut server, 1);
+    assert!(server.join_room(2, &room, None).is_ok());
+    assert!(server.disconnect(0));
+
+    assert!(server.resume(1, &token).is_some());
+    let rooms = server.list_rooms();
+    let info = rooms.iter().find(|info| info.code == room).unwrap();
+    assert_eq!(info.players, 1);
+    assert_eq!(info.owner, "Player 2");
+}
+
+#[test]
+fn lobby_sees_the_end_of_matches() {
+    let mut server = Server::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    connect(&mut server, 2);
+    let room = create_room(&mut server, 0);
+    assert!(server.join_room(1, &room, None).is_ok());
+    let settings = GameSettings { rounds_to_win: 1, ..Default::default() };
+    assert!(server.set_settings(0, settings).is_ok());
+    assert!(server.launch_game(0));
+    // The last player standing wins the only round
+    assert!(server.leave_room(1));
+    server.get_events(&2);
+
+    let mut running = true;
+    for _ in 0..200 {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        server.update_finished_matches();
+        for msg in decode_all(&server.get_events(&2)) {
+            if let ServerMessage::RoomUpdated(info) = msg {
+                assert_eq!(info.code, room);
+                running = info.running;
+            }
+        }
+        if !running {
+            break;
+        }
+    }
+    assert!(!running);
+    assert!(!server.list_rooms()[0].running);
+    // Only once
+    server.update_finished_matches();
+    assert!(server.get_events(&2).is_empty());
+}
+
+#[test]
+fn rooms_are_closed_when_players_leave() {
+    let mut server = Server::new();
+    connect(&mut server, 0);
+    connect(&mut server, 1);
+    let room = create_room(&mut server, 0);
+    assert!(server.join_room(1, &room, None).is_ok());
+    assert!(server.launch_game(0));
+
+    // The game goes on without the player who left
+    assert!(server.leave_room(1));
+    assert!(server.move_player(0, Direction::East));
+    // The game is stopped with the room
+    assert!(server.leave_room(0));
+    assert_eq!(server.join_room(1, &room, None), Err(JoinError::NotFound));
+}
+
+/**
+ * Names of the last members list received by a player
+ */
+fn room_names(server: &mut Server, id: u64) -> Vec<String> {
+    decode_all(&server.get_events(&id)).into_iter().filter_map(|msg| match msg {
+        ServerMessage::RoomPlayers(msg) => Some(msg.players.into_iter().map(|p| p.name).collect()),
+        _ => None,
+    }).last().unwrap()
+}
+
+#[test]
+fn players_have_unique_names_in_rooms() {
+    let mut server = Server::new();
+    for id in 0..3 {
+        connect(&mut server, id);
+    }
+    assert!(server.set_name(0, " Alice ").is_ok());
+    assert!(server.set_name(1, "alice").is_err());
+    assert!(server.set_name(1, "").is_err());
+    assert!(server.set_name(1, "A very very long name").is_err());
+    assert!(server.set_name(1, "Bob").is_ok());
+
+    let room = create_room(&mut server, 0);
+    assert!(server.join_room(1, &room, None).is_ok());
+    assert!(server.set_name(1, "Alice").is_err());
+    assert_eq!(room_names(&mut server, 0), vec!["Alice", "Bob"]);
+
+    // Names are only unique in a room
+    assert!(server.set_name(2, "Bob").is_ok());
+    assert!(server.join_room(2, &room, None).is_ok());
+    assert_eq!(room_names(&mut server, 1), vec!["Alice", "Bob", "Bob 2"]);
+    assert!(server.leave_room(0));
+    assert_eq!(room_names(&mut server, 1), vec!["Bob", "Bob 2"]);
+
+    // Numbers fit in the longest names
+    assert!(server.set_name(0, "Sixteen chars ok").is_ok());
+    assert!(server.set_name(1, "sixteen chars ok").is_ok());
+    let other = create_room(&mut server, 0);
+    assert!(server.join_room(1, &other, None).is_ok());
+    assert_eq!(room_names(&mut server, 1), vec!["Sixteen chars ok", "sixteen chars 2"]);
+}
+
+#[test]
+fn lobby_lists_and_follows_rooms() {
+    let mut server = Server::new();
+    for id in 0..3 {
+        connect(&mut server, id);
+    }
+    assert!(server.set_name(0, "Alice").is_ok());
+    let first = create_room(&mut server, 0);
+    let options = RoomOptions { name: Some(String::new()), ..Default::default() };
+    assert_eq!(server.create_room(1, options), 0);
+    let options = RoomOptions { name: Some(String::from("Arena")), ..Default::default() };
+    let second = server.create_room(1, options);
+    let second = server.room_code(second).unwrap();
+    assert!(server.set_settings(1, GameSettings {
+        generator: String::from("arena"),
+        ..Default::default()
+    }).is_ok());
+
+    let rooms = server.list_rooms();
+    assert_eq!(rooms.iter().map(|r| &r.code).collect::<Vec<&String>>(), vec![&first, &second]);
+    assert_eq!((&rooms[0].name[..], &rooms[0].owner[..]), ("Alice's room", "Alice"));
+    assert_eq!((rooms[0].players, rooms[0].capacity, rooms[0].running), (1, 4, false));
+    assert_eq!((&rooms[1].name[..], &rooms[1].generator[..]), ("Arena", "arena"));
+
+    // The lobby follows the rooms without asking
+    server.get_events(&2);
+    assert!(server.add_bot(0, Difficulty::Easy));
+    assert!(server.leave_room(1));
+    match &decode_all(&server.get_events(&2))[..] {
+        [ServerMessage::RoomUpdated(room), ServerMessage::RoomClosed(closed)] => {
+            assert_eq!((&room.code, room.players), (&first, 2));
+            assert_eq!(closed.code, second);
+        },
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    assert_eq!(server.list_rooms().len(), 1);
+}
+
+#[test]
+fn owners_manage_their_room() {
+    let mut server = Server::new();
+    for id in 0..3 {
+        connect(&mut server, id);
+    }
+    let room = create_room(&mut server, 0);
+    assert!(server.join_room(1, &room, None).is_ok());
+    assert!(server.join_room(2, &room, None).is_ok());
+
+    // Only the owner configures, kicks and launches
+    assert!(!server.add_bot(1, Difficulty::Easy));
+    assert!(!server.set_teams(1, 2, false));
+    assert!(!server.kick_player(1, 2));
+    assert!(!server.launch_game(1));
+    assert!(!server.kick_player(0, 0));
+    assert!(server.kick_player(0, 2));
+    assert!(decode_all(&server.get_events(&2)).iter().any(|msg| *msg == ServerMessage::Kicked(KickedMsg::new(room.clone()))));
+    assert!(!server.kick_player(0, 2));
+    assert_eq!(server.list_rooms()[0].players, 2);
+
+    // The room goes to another member when the owner leaves
+    assert!(server.join_room(2, &room, None).is_ok());
+    assert!(server.leave_room(0));
+    assert!(!server.launch_game(2));
+    assert!(server.disconnect(1));
+    assert!(server.add_bot(2, Difficulty::Easy));
+    assert!(server.launch_game(2));
+}
+
+#[test]
+fn games_start_when_everybody_is_ready() {
+    let mut server = Server::new();
+    for id in 0..3 {
+        connect(&mut server, id);
+    }
+    let room = create_room(&mut server, 0);
+    assert!(server.join_room(1, &room, None).is_ok());
+    assert!(!server.set_ready(2, true));
+    assert!(server.set_ready(0, true));
+    assert!(server.set_ready(1, false));
+    assert!(!server.list_rooms()[0].running);
+
+    // Bots are always ready
+    assert!(server.add_bot(0, Difficulty::Easy));
+    assert!(server.set_ready(1, true));
+    assert!(server.list_rooms()[0].running);
+    assert!(!server.set_ready(1, false));
+}
+
+#[test]
+fn matches_start_with_a_countdown() {
+    let mut game_match = Match::new(DEFAULT_TICK_RATE, GameSettings::default());
+    let streams = vec![Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new()))];
+    for (idx, rx) in streams.iter().enumerate() {
+        game_match.link_player(Player {
+            rx: rx.clone(),
+            name: format!("Player {}", idx),
+            team: None,
+            bot: None,
+            ready: true,
+        });
+    }
+    game_match.start();
+    for _ in 0..3 * DEFAULT_TICK_RATE {
+        game_match.push_action(Action::PutBomb, 0);
+        game_match.event_loop();
+    }
+    assert!(game_match.game.replay().actions.is_empty());
+
+    let msgs = decode_all(&streams[0].lock().unwrap());
+    let countdown: Vec<u64> = msgs.iter().filter_map(|msg| match msg {
+        ServerMessage::Countdown(Countdown { seconds }) => Some(*seconds),
+        _ => None,
+    }).collect();
+    assert_eq!(countdown, vec![3, 2, 1]);
+    match &msgs[..3] {
+        [ServerMessage::PlayerIdentity(_), ServerMessage::Map(_), ServerMessage::Countdown(_)] => {},
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+
+    // The round starts after the countdown
+    game_match.event_loop();
+    game_match.push_action(Action::PutBomb, 0);
+    assert_eq!(game_match.game.replay().actions.len(), 1);
+}
+
+#[test]
+fn private_rooms_need_their_code() {
+    let mut server = Server::new();
+    for id in 0..4 {
+        connect(&mut server, id);
+    }
+    let options = RoomOptions {
+        password: Some(String::from("secret")),
+        private: true,
+        ..Default::default()
+    };
+    let room = server.create_room(0, options);
+    let code = server.room_code(room).unwrap();
+    assert_ne!(code, room.to_string());
+    assert!(server.list_rooms().is_empty());
+    assert!(server.get_events(&3).is_empty());
+
+    assert_eq!(server.join_room(1, &room.to_string(), Some("secret")), Err(JoinError::NotFound));
+    assert_eq!(server.join_room(1, &code, None), Err(JoinError::WrongPassword));
+    assert_eq!(server.join_room(1, &code, Some("Secret")), Err(JoinError::WrongPassword));
+    assert!(server.join_room(1, &code, Some("secret")).is_ok());
+    assert_eq!(server.join_room(1, &code, Some("secret")), Err(JoinError::AlreadyInRoom));
+
+    // Players stay in their room when they can't join another one
+    let other = create_room(&mut server, 2);
+    assert!(server.set_settings(2, GameSettings { players: 2, ..Default::default() }).is_ok());
+    assert!(server.join_room(3, &other, None).is_ok());
+    assert_eq!(server.join_room(1, &other, None), Err(JoinError::Full));
+    assert!(server.set_ready(2, true) && server.set_ready(3, true));
+    assert!(server.leave_room(3));
+    assert_eq!(server.join_room(1, &other, None), Err(JoinError::Running));
+    assert_eq!(room_names(&mut server, 0), vec!["Player 0", "Player 1"]);
+}
+
+#[test]
+fn stopped_matches_do_not_start() {
+    let mut game_match = Match::new(DEFAULT_TICK_RATE, GameSettings::default());
+    game_match.stop();
+    game_match.start();
+    game_match.event_loop();
+    assert!(game_match.finished());
+}
+
+#[test]
+fn matches_are_won_after_enough_rounds() {
+    let settings = GameSettings { rounds_to_win: 2, ..Default::default() };
+    let mut game_match = Match::new(DEFAULT_TICK_RATE, settings);
+    let streams = vec![Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new()))];
+    for (idx, rx) in streams.iter().enumerate() {
+        game_match.link_player(Player {
+            rx: rx.clone(),
+            name: format!("Player {}", idx),
+            team: None,
+            bot: None,
+            ready: true,
+        });
+    }
+    game_match.start();
+    let mut msgs = Vec::new();
+    for _ in 0..20 * DEFAULT_TICK_RATE {
+        if game_match.finished() {
+            break;
+        }
+        // The second player gives up each round
+        game_match.push_action(Action::Leave, 1);
+        game_match.event_loop();
+        msgs.append(&mut decode_all(&streams[0].lock().unwrap()));
+        streams[0].lock().unwrap().clear();
+    }
+    assert!(game_match.finished());
+
+    let rounds: Vec<_> = msgs.iter().filter_map(|msg| match msg {
+        ServerMessage::RoundOver(round) => Some((round.round, round.winner, round.wins.clone())),
+        _ => None,
+    }).collect();
+    assert_eq!(rounds, vec![(1, Some(0), vec![1, 0]), (2, Some(0), vec![2, 0])]);
+    // A fresh map for the second round, after the intermission
+    let intermission: Vec<u64> = msgs.iter().filter_map(|msg| match msg {
+        ServerMessage::Intermission(intermission) => Some(intermission.seconds),
+        _ => None,
+    }).collect();
+    assert_eq!(intermission, vec![5, 4, 3, 2, 1]);
+    assert_eq!(msgs.iter().filter(|msg| matches!(msg, ServerMessage::Map(_))).count(), 2);
+    match msgs.last() {
+        Some(ServerMessage::MatchOver(over)) => {
+            assert_eq!(over.winner, Some(0));
+            assert_eq!(over.wins, vec![2, 0]);
+        },
+        msg => panic!("Unexpected message {:?}", msg),
+    }
+}
+
+#[test]
+fn bots_take_over_the_stream_of_players_who_left() {
+    let settings = GameSettings { fill_bots: Some(Difficulty::Easy), ..Default::default() };
+    let mut game_match = Match::new(DEFAULT_TICK_RATE, settings);
+    let streams = vec![Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new()))];
+    for (idx, rx) in streams.iter().enumerate() {
+        game_match.link_player(Player {
+            rx: rx.clone(),
+            name: format!("Player {}", idx),
+            team: None,
+            bot: None,
+            ready: true,
+        });
+    }
+    game_match.start();
+    assert!(game_match.remove_player(0));
+    // Only one bot takes over
+    assert!(!game_match.remove_player(0));
+    streams[0].lock().unwrap().clear();
+    // During the countdown and the game
+    for _ in 0..5 * DEFAULT_TICK_RATE {
+        assert!(streams[0].lock().unwrap().is_empty());
+        game_match.event_loop();
+    }
+    assert!(streams[0].lock().unwrap().is_empty());
+    assert!(!streams[1].lock().unwrap().is_empty());
+}
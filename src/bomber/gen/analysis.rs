@@ -0,0 +1,247 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::map::Map;
+use super::utils::SquareType;
+
+// Minimum ratio between the worst and the best spawn for the room around it
+const MIN_ROOM_RATIO: f32 = 0.25;
+// Minimum ratio between the worst and the best spawn for the distance to opponents
+const MIN_DISTANCE_RATIO: f32 = 0.5;
+// Maximum difference of distance to the first box between spawns
+const MAX_BOX_DISTANCE_GAP: usize = 3;
+
+/**
+ * What a player can do from its spawn at the beginning of a game
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SpawnMetrics {
+    pub reachable_area: usize,              /* squares reachable without breaking boxes */
+    pub reachable_boxes: usize,             /* boxes next to the reachable area */
+    pub nearest_box: Option<usize>,         /* path length to the first square next to a box */
+    pub nearest_opponent: Option<usize>,    /* path length to another spawn, boxes broken */
+}
+
+/**
+ * Metrics of every spawn of a map
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct MapAnalysis {
+    pub spawns: Vec<SpawnMetrics>,
+    pub spawn_distances: Vec<Vec<Option<usize>>>,   /* path length between spawns, boxes broken */
+}
+
+/**
+ * Compute path lengths from a square
+ * @param map           The map
+ * @param start         The starting square
+ * @param through_boxes If boxes are considered broken
+ * @return              The path length to each square, None if unreachable
+ */
+fn distances(map: &Map, start: (usize, usize), through_boxes: bool) -> Vec<Option<usize>> {
+    let mut res = vec![None; map.w * map.h];
+    let mut to_check = VecDeque::new();
+    res[start.0 + start.1 * map.w] = Some(0);
+    to_check.push_back(start);
+    while let Some((x, y)) = to_check.pop_front() {
+        let dist = res[x + y * map.w].unwrap();
+        let mut next = Vec::new();
+        if x > 0 {
+            next.push((x - 1, y));
+        }
+        if x + 1 < map.w {
+            next.push((x + 1, y));
+        }
+        if y > 0 {
+            next.push((x, y - 1));
+        }
+        if y + 1 < map.h {
+            next.push((x, y + 1));
+        }
+        for (nx, ny) in next {
+            let idx = nx + ny * map.w;
+            if res[idx].is_some() || !walkable(map, idx, through_boxes) {
+                continue;
+            }
+            res[idx] = Some(dist + 1);
+            to_check.push_back((nx, ny));
+        }
+    }
+    res
+}
+
+/**
+ * Walls are considered crossable, players can go around
+ */
+fn walkable(map: &Map, idx: usize, through_boxes: bool) -> bool {
+    match map.squares[idx].sq_type {
+        SquareType::Block | SquareType::Water => return false,
+        _ => {}
+    }
+    match &map.items[idx] {
+        Some(item) => match item.name().as_str() {
+            "Bonus" | "Malus" => true,
+            "DestructibleBox" => through_boxes,
+            _ => false,
+        },
+        None => true,
+    }
+}
+
+fn is_box(map: &Map, idx: usize) -> bool {
+    map.items[idx].as_ref().map_or(false, |i| i.name() == "DestructibleBox")
+}
+
+fn neighbours(map: &Map, idx: usize) -> Vec<usize> {
+    let (x, y) = (idx % map.w, idx / map.w);
+    let mut res = Vec::new();
+    if x > 0 {
+        res.push(idx - 1);
+    }
+    if x + 1 < map.w {
+        res.push(idx + 1);
+    }
+    if y > 0 {
+        res.push(idx - map.w);
+    }
+    if y + 1 < map.h {
+        res.push(idx + map.w);
+    }
+    res
+}
+
+impl MapAnalysis {
+    /**
+     * Analyse the spawns of a map
+     * @param map   The map to analyse
+     * @return      The metrics of the map
+     */
+    pub fn new(map: &Map) -> MapAnalysis {
+        let positions: Vec<(usize, usize)> = map.players.iter()
+            .map(|p| (p.x as usize, p.y as usize))
+            .collect();
+        let mut spawn_distances: Vec<Vec<Option<usize>>> = Vec::new();
+        for pos in &positions {
+            let through = distances(map, *pos, true);
+            spawn_distances.push(positions.iter().map(|p| through[p.0 + p.1 * map.w]).collect());
+        }
+        let mut spawns = Vec::new();
+        for (idx, pos) in positions.iter().enumerate() {
+            let reachable = distances(map, *pos, false);
+            let mut reachable_area = 0;
+            let mut boxes = Vec::new();
+            let mut nearest_box = None;
+            for square in 0..reachable.len() {
+                let dist = match reachable[square] {
+                    Some(dist) => dist,
+                    None => continue,
+                };
+                reachable_area += 1;
+                for n in neighbours(map, square) {
+                    if is_box(map, n) {
+                        if !boxes.contains(&n) {
+                            boxes.push(n);
+                        }
+                        nearest_box = Some(nearest_box.map_or(dist, |d: usize| d.min(dist)));
+                    }
+                }
+            }
+            let nearest_opponent = spawn_distances[idx].iter().enumerate()
+                .filter(|(other, _)| *other != idx)
+                .filter_map(|(_, dist)| *dist)
+                .min();
+            spawns.push(SpawnMetrics {
+                reachable_area,
+                reachable_boxes: boxes.len(),
+                nearest_box,
+                nearest_opponent,
+            });
+        }
+        MapAnalysis {
+            spawns,
+            spawn_distances,
+        }
+    }
+
+    /**
+     * Compare the worst spawn to the best one for the room around it, the boxes
+     * it can break and the distance to its opponents
+     * @return the lowest ratio between the worst and the best spawn, relative to
+     *         the accepted ratio: 0 if some spawns can't reach each other, 1 if
+     *         the spawns are balanced enough
+     */
+    pub fn balance(&self) -> f32 {
+        let connected = self.spawn_distances.iter().all(|d| d.iter().all(|d| d.is_some()));
+        if !connected {
+            return 0.;
+        }
+        let ratio = |values: Vec<usize>, expected: f32| {
+            let min = *values.iter().min().unwrap_or(&0) as f32;
+            let max = *values.iter().max().unwrap_or(&0) as f32;
+            if max == 0. {
+                1.
+            } else {
+                // Normalized, so each metric is fair from 1
+                (min / max / expected).min(1.)
+            }
+        };
+        ratio(self.spawns.iter().map(|s| s.reachable_area).collect(), MIN_ROOM_RATIO)
+            .min(ratio(self.spawns.iter().map(|s| s.reachable_boxes).collect(), MIN_ROOM_RATIO))
+            .min(ratio(self.spawns.iter().filter_map(|s| s.nearest_opponent).collect(), MIN_DISTANCE_RATIO))
+    }
+
+    /**
+     * A map is fair when every spawn can reach the others and when no spawn
+     * has much less room, boxes or distance to its opponents than another one
+     * @return if the map is fair
+     */
+    pub fn is_fair(&self) -> bool {
+        let nearest_boxes: Vec<Option<usize>> = self.spawns.iter().map(|s| s.nearest_box).collect();
+        let boxes_fair = if nearest_boxes.iter().all(|d| d.is_some()) {
+            let d: Vec<usize> = nearest_boxes.iter().map(|d| d.unwrap()).collect();
+            d.iter().max().unwrap_or(&0) - d.iter().min().unwrap_or(&0) <= MAX_BOX_DISTANCE_GAP
+        } else {
+            nearest_boxes.iter().all(|d| d.is_none())
+        };
+        boxes_fair && self.balance() >= 1.
+    }
+}
+
+impl fmt::Display for MapAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "spawn  area  boxes  nearest box  nearest opponent")?;
+        let opt = |v: Option<usize>| v.map_or(String::from("-"), |v| v.to_string());
+        for (idx, s) in self.spawns.iter().enumerate() {
+            writeln!(f, "{:>5}  {:>4}  {:>5}  {:>11}  {:>16}",
+                idx, s.reachable_area, s.reachable_boxes, opt(s.nearest_box), opt(s.nearest_opponent))?;
+        }
+        write!(f, "fair: {}", self.is_fair())
+    }
+}
@@ -0,0 +1,63 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::{Rng, RngCore};
+
+use super::MapGenerator;
+use super::super::item::{DestructibleBox, InteractiveItem};
+use super::super::utils::{Square, SquareType};
+
+/**
+ * An open arena: a few pillars and a few boxes, for fast fights
+ */
+pub struct ArenaGenerator;
+
+impl MapGenerator for ArenaGenerator {
+    fn name(&self) -> &'static str {
+        "arena"
+    }
+
+    fn generate(&self, w: usize, h: usize, rng: &mut dyn RngCore) -> (Vec<Square>, Vec<Option<InteractiveItem>>) {
+        let mut squares = Vec::with_capacity(w * h);
+        let mut items: Vec<Option<InteractiveItem>> = Vec::with_capacity(w * h);
+        for idx in 0..(w * h) {
+            let (x, y) = (idx % w, idx / w);
+            let sq_type = if x % 4 == 2 && y % 4 == 2 {
+                SquareType::Block
+            } else {
+                SquareType::Empty
+            };
+            if sq_type == SquareType::Empty && rng.gen_range(0, 5) == 0 {
+                items.push(Some(Box::new(DestructibleBox {})));
+            } else {
+                items.push(None);
+            }
+            squares.push(Square { sq_type });
+        }
+        (squares, items)
+    }
+}
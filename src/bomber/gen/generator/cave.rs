@@ -0,0 +1,112 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::{Rng, RngCore};
+
+use super::MapGenerator;
+use super::super::item::{DestructibleBox, InteractiveItem};
+use super::super::utils::{Square, SquareType};
+
+// Part of the map filled with rocks before smoothing
+const FILL_PERCENT: u32 = 40;
+const SMOOTHING_STEPS: usize = 4;
+
+/**
+ * Organic caves made with a cellular automaton: random rocks are smoothed,
+ * a square becomes a rock if most of its neighbours are rocks.
+ */
+pub struct CaveGenerator;
+
+fn rocks_around(rocks: &[bool], w: usize, h: usize, x: usize, y: usize) -> usize {
+    let mut count = 0;
+    for dy in -1i32..2 {
+        for dx in -1i32..2 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            // Outside of the map is open, so no spawn is walled in a corner
+            if nx >= 0 && ny >= 0 && nx < w as i32 && ny < h as i32 && rocks[nx as usize + ny as usize * w] {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+impl MapGenerator for CaveGenerator {
+    fn name(&self) -> &'static str {
+        "cave"
+    }
+
+    fn generate(&self, w: usize, h: usize, rng: &mut dyn RngCore) -> (Vec<Square>, Vec<Option<InteractiveItem>>) {
+        let mut rocks: Vec<bool> = (0..(w * h)).map(|_| rng.gen_range(0, 100) < FILL_PERCENT).collect();
+        for _ in 0..SMOOTHING_STEPS {
+            let mut next = rocks.clone();
+            for y in 0..h {
+                for x in 0..w {
+                    let around = rocks_around(&rocks, w, h, x, y);
+                    if around > 4 {
+                        next[x + y * w] = true;
+                    } else if around < 4 {
+                        next[x + y * w] = false;
+                    }
+                }
+            }
+            rocks = next;
+        }
+        // Spawns are on the border, keep it open so every player can reach the others
+        let on_border = |idx: usize| {
+            let (x, y) = (idx % w, idx / w);
+            x == 0 || y == 0 || x == w - 1 || y == h - 1
+        };
+        for (idx, rock) in rocks.iter_mut().enumerate() {
+            if on_border(idx) {
+                *rock = false;
+            }
+        }
+
+        let mut squares = Vec::with_capacity(w * h);
+        let mut items: Vec<Option<InteractiveItem>> = Vec::with_capacity(w * h);
+        for (idx, rock) in rocks.into_iter().enumerate() {
+            if rock {
+                squares.push(Square { sq_type: SquareType::Block });
+                items.push(None);
+            } else {
+                squares.push(Square { sq_type: SquareType::Empty });
+                // No boxes on the border, so every spawn starts with the same room
+                if !on_border(idx) && rng.gen_range(0, 2) == 0 {
+                    items.push(Some(Box::new(DestructibleBox {})));
+                } else {
+                    items.push(None);
+                }
+            }
+        }
+        (squares, items)
+    }
+}
@@ -0,0 +1,106 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::{Rng, RngCore};
+
+use super::MapGenerator;
+use super::super::item::{DestructibleBox, InteractiveItem};
+use super::super::utils::{Square, SquareType};
+
+/**
+ * A maze carved between even positions. Walls of the maze are mostly boxes,
+ * so players can open shortcuts, and a few boxes block corridors.
+ */
+pub struct MazeGenerator;
+
+impl MapGenerator for MazeGenerator {
+    fn name(&self) -> &'static str {
+        "maze"
+    }
+
+    fn generate(&self, w: usize, h: usize, rng: &mut dyn RngCore) -> (Vec<Square>, Vec<Option<InteractiveItem>>) {
+        // Everything is closed, then corridors are carved
+        let mut open = vec![false; w * h];
+        let cells_w = (w + 1) / 2;
+        let cells_h = (h + 1) / 2;
+        let mut visited = vec![false; cells_w * cells_h];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        open[0] = true;
+        while let Some(&(cx, cy)) = stack.last() {
+            let mut next = Vec::new();
+            if cx > 0 && !visited[cx - 1 + cy * cells_w] {
+                next.push((cx - 1, cy));
+            }
+            if cx + 1 < cells_w && !visited[cx + 1 + cy * cells_w] {
+                next.push((cx + 1, cy));
+            }
+            if cy > 0 && !visited[cx + (cy - 1) * cells_w] {
+                next.push((cx, cy - 1));
+            }
+            if cy + 1 < cells_h && !visited[cx + (cy + 1) * cells_w] {
+                next.push((cx, cy + 1));
+            }
+            if next.is_empty() {
+                stack.pop();
+                continue;
+            }
+            let (nx, ny) = next[rng.gen_range(0, next.len())];
+            visited[nx + ny * cells_w] = true;
+            // Open the cell and the square between both cells
+            open[nx * 2 + ny * 2 * w] = true;
+            open[(cx + nx) + (cy + ny) * w] = true;
+            stack.push((nx, ny));
+        }
+
+        let mut squares = Vec::with_capacity(w * h);
+        let mut items: Vec<Option<InteractiveItem>> = Vec::with_capacity(w * h);
+        for idx in 0..(w * h) {
+            let (x, y) = (idx % w, idx / w);
+            let mut sq_type = SquareType::Empty;
+            let mut add_box = false;
+            if x % 2 == 1 && y % 2 == 1 {
+                sq_type = SquareType::Block;
+            } else if !open[idx] {
+                if rng.gen_range(0, 3) == 0 {
+                    sq_type = SquareType::Block;
+                } else {
+                    add_box = true;
+                }
+            } else {
+                add_box = rng.gen_range(0, 4) == 0;
+            }
+            squares.push(Square { sq_type });
+            if add_box {
+                items.push(Some(Box::new(DestructibleBox {})));
+            } else {
+                items.push(None);
+            }
+        }
+        (squares, items)
+    }
+}
@@ -0,0 +1,74 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::RngCore;
+
+use super::{ClassicGenerator, MapGenerator};
+use super::super::item::InteractiveItem;
+use super::super::utils::{Direction, Square, SquareType};
+
+/**
+ * A classic map where the top left quarter is mirrored to the other quarters,
+ * so every spawn gets the same surroundings
+ */
+pub struct MirroredGenerator;
+
+/**
+ * Walls must face the other way once mirrored
+ */
+fn mirror(sq_type: SquareType, horizontal: bool, vertical: bool) -> SquareType {
+    match sq_type {
+        SquareType::Wall(Direction::West) if horizontal => SquareType::Wall(Direction::East),
+        SquareType::Wall(Direction::East) if horizontal => SquareType::Wall(Direction::West),
+        SquareType::Wall(Direction::North) if vertical => SquareType::Wall(Direction::South),
+        SquareType::Wall(Direction::South) if vertical => SquareType::Wall(Direction::North),
+        _ => sq_type,
+    }
+}
+
+impl MapGenerator for MirroredGenerator {
+    fn name(&self) -> &'static str {
+        "mirrored"
+    }
+
+    fn generate(&self, w: usize, h: usize, rng: &mut dyn RngCore) -> (Vec<Square>, Vec<Option<InteractiveItem>>) {
+        let (mut squares, mut items) = ClassicGenerator.generate(w, h, rng);
+        for y in 0..h {
+            for x in 0..w {
+                let src_x = x.min(w - 1 - x);
+                let src_y = y.min(h - 1 - y);
+                if (src_x, src_y) == (x, y) {
+                    continue;
+                }
+                let src = src_x + src_y * w;
+                squares[x + y * w].sq_type = mirror(squares[src].sq_type, src_x != x, src_y != y);
+                items[x + y * w] = items[src].clone();
+            }
+        }
+        (squares, items)
+    }
+}
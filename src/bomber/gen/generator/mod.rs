@@ -0,0 +1,74 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::RngCore;
+
+use super::item::InteractiveItem;
+use super::utils::Square;
+
+pub mod arena;
+pub use arena::ArenaGenerator;
+pub mod cave;
+pub use cave::CaveGenerator;
+pub mod classic;
+pub use classic::ClassicGenerator;
+pub mod maze;
+pub use maze::MazeGenerator;
+pub mod mirrored;
+pub use mirrored::MirroredGenerator;
+
+/**
+ * Generate the squares and the items of a map. Players are spawned by
+ * the map afterwards, which then makes sure every player can play.
+ */
+pub trait MapGenerator {
+    fn name(&self) -> &'static str;
+
+    /**
+     * @param w     width of the map
+     * @param h     height of the map
+     * @param rng   random generator used for the generation
+     * @return      The squares and items of the map, in reading order
+     */
+    fn generate(&self, w: usize, h: usize, rng: &mut dyn RngCore) -> (Vec<Square>, Vec<Option<InteractiveItem>>);
+}
+
+/**
+ * @param name  The name of a generator (classic, mirrored, maze, cave or arena)
+ * @return      The generator, None if unknown
+ */
+pub fn generator_by_name(name: &str) -> Option<Box<dyn MapGenerator>> {
+    let generator: Box<dyn MapGenerator> = match name {
+        "classic" => Box::new(ClassicGenerator),
+        "mirrored" => Box::new(MirroredGenerator),
+        "maze" => Box::new(MazeGenerator),
+        "cave" => Box::new(CaveGenerator),
+        "arena" => Box::new(ArenaGenerator),
+        _ => return None,
+    };
+    Some(generator)
+}
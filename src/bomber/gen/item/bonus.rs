@@ -40,16 +40,20 @@ pub enum Bonus {
     ImproveSpeed,
     RepelBombs,
     MoreBombs,
+    SquareBombs,
+    CircleBombs,
     Custom(String)
 }
 
 impl Distribution<Bonus> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Bonus {
-        match rng.gen_range(0, 4) {
+        match rng.gen_range(0, 7) {
             0 => Bonus::ImproveBombRadius,
             1 => Bonus::PunchBombs,
             2 => Bonus::ImproveSpeed,
             3 => Bonus::RepelBombs,
+            4 => Bonus::SquareBombs,
+            5 => Bonus::CircleBombs,
             _ => Bonus::MoreBombs,
         }
     }
@@ -0,0 +1,176 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use super::{Bonus, InteractiveItem, Malus};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * What a destroyed box can leave
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Drop {
+    Nothing,
+    Bonus(Bonus),
+    Malus(Malus),
+}
+
+impl FromStr for Drop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Drop, String> {
+        let drop = match s {
+            "Nothing" => Drop::Nothing,
+            "ImproveBombRadius" => Drop::Bonus(Bonus::ImproveBombRadius),
+            "PunchBombs" => Drop::Bonus(Bonus::PunchBombs),
+            "ImproveSpeed" => Drop::Bonus(Bonus::ImproveSpeed),
+            "RepelBombs" => Drop::Bonus(Bonus::RepelBombs),
+            "MoreBombs" => Drop::Bonus(Bonus::MoreBombs),
+            "SquareBombs" => Drop::Bonus(Bonus::SquareBombs),
+            "CircleBombs" => Drop::Bonus(Bonus::CircleBombs),
+            "Slow" => Drop::Malus(Malus::Slow),
+            "UltraFast" => Drop::Malus(Malus::UltraFast),
+            "SpeedBomb" => Drop::Malus(Malus::SpeedBomb),
+            "DropBombs" => Drop::Malus(Malus::DropBombs),
+            "InvertedControls" => Drop::Malus(Malus::InvertedControls),
+            _ => return Err(format!("Unknown drop {}", s)),
+        };
+        Ok(drop)
+    }
+}
+
+impl fmt::Display for Drop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Drop::Nothing => write!(f, "Nothing"),
+            Drop::Bonus(bonus) => write!(f, "{:?}", bonus),
+            Drop::Malus(malus) => write!(f, "{:?}", malus),
+        }
+    }
+}
+
+/**
+ * Weighted drops of destroyed boxes
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DropTable {
+    pub drops: Vec<(Drop, u32)>,
+}
+
+impl Default for DropTable {
+    /**
+     * 2/5 chance to drop a bonus, 1/5 to drop a malus
+     */
+    fn default() -> DropTable {
+        let mut drops = vec![(Drop::Nothing, 56)];
+        for bonus in &[
+            Bonus::ImproveBombRadius, Bonus::PunchBombs, Bonus::ImproveSpeed, Bonus::RepelBombs,
+            Bonus::MoreBombs, Bonus::SquareBombs, Bonus::CircleBombs,
+        ] {
+            drops.push((Drop::Bonus(bonus.clone()), 8));
+        }
+        for malus in &[Malus::Slow, Malus::UltraFast, Malus::SpeedBomb, Malus::DropBombs] {
+            drops.push((Drop::Malus(malus.clone()), 7));
+        }
+        DropTable { drops }
+    }
+}
+
+impl DropTable {
+    /**
+     * @return the sum of all weights, None if it overflows
+     */
+    pub fn total(&self) -> Option<u32> {
+        self.drops.iter().try_fold(0u32, |total, (_, weight)| total.checked_add(*weight))
+    }
+
+    /**
+     * Check that a box can roll the table
+     * @return  If the table is valid, or why it is invalid
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        match self.total() {
+            None => Err(String::from("Drop weights are too large")),
+            Some(0) => Err(String::from("Drop table is empty")),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /**
+     * Choose the item dropped by a box
+     * @param rng   The random generator
+     * @return      The dropped item, if any
+     */
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> Option<InteractiveItem> {
+        let total = match self.total() {
+            Some(total) if total > 0 => total,
+            _ => return None,
+        };
+        let mut roll = rng.gen_range(0, total);
+        for (drop, weight) in &self.drops {
+            if roll < *weight {
+                return match drop {
+                    Drop::Nothing => None,
+                    Drop::Bonus(bonus) => Some(Box::new(bonus.clone())),
+                    Drop::Malus(malus) => Some(Box::new(malus.clone())),
+                };
+            }
+            roll -= weight;
+        }
+        None
+    }
+}
+
+/**
+ * Parse a drop table like "MoreBombs:2 Slow:1 Nothing:3"
+ */
+impl FromStr for DropTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DropTable, String> {
+        let mut drops = Vec::new();
+        for entry in s.split_whitespace() {
+            let mut parts = entry.splitn(2, ':');
+            let drop: Drop = parts.next().unwrap_or_default().parse()?;
+            let weight = parts.next()
+                .and_then(|w| w.parse::<u32>().ok())
+                .ok_or_else(|| format!("Invalid weight for {}", drop))?;
+            drops.push((drop, weight));
+        }
+        Ok(DropTable { drops })
+    }
+}
+
+impl fmt::Display for DropTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries: Vec<String> = self.drops.iter()
+            .map(|(drop, weight)| format!("{}:{}", drop, weight))
+            .collect();
+        write!(f, "{}", entries.join(" "))
+    }
+}
@@ -75,5 +75,7 @@ pub mod bonus;
 pub use bonus::Bonus;
 pub mod destructiblebox;
 pub use destructiblebox::DestructibleBox;
+pub mod droptable;
+pub use droptable::{Drop, DropTable};
 pub mod malus;
 pub use malus::Malus;
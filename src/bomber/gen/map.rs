@@ -27,11 +27,17 @@
 
 use std::fmt;
 use std::f64::consts::PI;
+use std::str::FromStr;
 use rand::Rng;
 
+use super::analysis::MapAnalysis;
+use super::generator::{ClassicGenerator, MapGenerator};
 use super::utils::{Direction, MapPlayer, Square, SquareType};
 use super::item::*;
 
+// Maps generated before keeping the most balanced one
+const MAX_GENERATION_ATTEMPTS: usize = 20;
+
 /**
  * Represent a map for a game
  */
@@ -46,79 +52,71 @@ pub struct Map {
 
 impl Map {
     /**
-     * Generate a new map.
-     * @todo redo and clean. Mostly avoid to generate players here
-     * @param w     width of the map
-     * @param h     height of the map
-     * @return      The generated map
+     * Generate a new classic map.
+     * @param w         width of the map
+     * @param h         height of the map
+     * @param players   number of players to spawn
+     * @param rng       random generator used for the generation
+     * @return          The generated map
      */
-    pub fn new(mut w: usize, mut h: usize) -> Map {
+    pub fn new<R: Rng>(w: usize, h: usize, players_nb: usize, rng: &mut R) -> Map {
+        Map::generate(&ClassicGenerator, w, h, players_nb, rng)
+    }
+
+    /**
+     * Generate a new map, then spawn players and make sure they can play.
+     * Maps where some players are disadvantaged are generated again, if no
+     * fair map is found, a classic map is generated instead. If even classic
+     * maps are unfair, the most balanced one is kept.
+     * @param generator The generator of squares and items
+     * @param w         width of the map
+     * @param h         height of the map
+     * @param players   number of players to spawn
+     * @param rng       random generator used for the generation
+     * @return          The generated map
+     */
+    pub fn generate<R: Rng>(generator: &dyn MapGenerator, w: usize, h: usize, players_nb: usize, rng: &mut R) -> Map {
+        let mut best: Option<(Map, f32)> = None;
+        for _ in 0..MAX_GENERATION_ATTEMPTS {
+            let map = Map::generate_once(generator, w, h, players_nb, rng);
+            let analysis = MapAnalysis::new(&map);
+            if analysis.is_fair() {
+                return map;
+            }
+            let balance = analysis.balance();
+            if best.as_ref().map_or(true, |(_, b)| balance > *b) {
+                best = Some((map, balance));
+            }
+        }
+        if generator.name() != ClassicGenerator.name() {
+            warn!("No fair {} map found, generate a classic map instead", generator.name());
+            return Map::generate(&ClassicGenerator, w, h, players_nb, rng);
+        }
+        warn!("No fair {} map found, keep the most balanced one", generator.name());
+        best.unwrap().0
+    }
+
+    fn generate_once<R: Rng>(generator: &dyn MapGenerator, mut w: usize, mut h: usize, players_nb: usize, rng: &mut R) -> Map {
         if w < 11 {
             w = 11;
         }
         if h < 11 {
             h = 11;
         }
-        let size = (w * h) as usize;
-        let mut squares = Vec::with_capacity(size);
-        let mut items: Vec<Option<InteractiveItem>> = Vec::with_capacity(size);
+        let (mut squares, mut items) = generator.generate(w, h, rng);
         let mut players = Vec::new();
-        let mut x = 0;
-        let mut y = 0;
-        let mut rng = rand::thread_rng();
-        for _ in 0..size {
-            let mut sq_type = rand::random();
-            if x % 2 == 1 && y % 2 == 1 {
-                sq_type = SquareType::Block;
-            }
-
-            let add_box: u8 = rng.gen();
-            if add_box % 3 != 0 && sq_type == SquareType::Empty {
-                items.push(Some(Box::new(DestructibleBox {})));
-            } else {
-                items.push(None);
-            }
-            squares.push(Square {
-                sq_type
-            });
-
-            // Next square
-            x += 1;
-            x %= w;
-            if x == 0 {
-                y += 1;
-            }
-        }
         // Generate players
-        for p in 0..4 {
-            let mut valid_pos = false;
-            let mut posx: usize = 0;
-            let mut posy: usize = 0;
-            let mut player = MapPlayer {
-                x: 0.5,
-                y: 0.5,
+        for (posx, posy) in Map::spawn_positions(w, h, players_nb) {
+            let player = MapPlayer {
+                x: posx as f32 + 0.5,
+                y: posy as f32 + 0.5,
                 radius: 2,
                 speed_factor: 1000,
                 bomb: 1,
                 dead: false,
+                team: None,
             };
-            while !valid_pos {
-                let random_x : usize = rng.gen();
-                let random_y : usize = rng.gen();
-                posx = random_x % (w / 4);
-                posy = random_y % (h / 4);
-                if p == 1 || p == 3 {
-                    posx = w - posx - 1;
-                }
-                if p == 2 || p == 3 {
-                    posy = h - posy - 1;
-                }
-                player.x = posx as f32 + 0.5;
-                player.y = posy as f32 + 0.5;
-                if squares[posx + posy * w].sq_type.walkable(&player, &(posx as f32, posy as f32)) {
-                    valid_pos = true;
-                }
-            }
+            squares[posx + posy * w].sq_type = SquareType::Empty;
             items[posx + posy * w] = None;
             players.push(player);
         }
@@ -129,18 +127,50 @@ impl Map {
             players,
             items
         };
-        res.make_startable();
+        res.make_startable(rng);
         res
     }
 
-    fn player_can_move_x_y(&self, p: &MapPlayer) -> bool {
+    /**
+     * Spread players evenly on the border of the map. Players are placed
+     * at regular angles around the center, projected on the border, so
+     * 4 players start in the corners and 8 players in corners and middles.
+     * @param w         width of the map
+     * @param h         height of the map
+     * @param players   number of players to spawn
+     * @return          The spawn positions
+     */
+    pub fn spawn_positions(w: usize, h: usize, players: usize) -> Vec<(usize, usize)> {
+        let half_w = (w - 1) as f64 / 2.;
+        let half_h = (h - 1) as f64 / 2.;
+        let mut positions = Vec::with_capacity(players);
+        for p in 0..players {
+            let angle = PI / 4. + 2. * PI * p as f64 / players as f64;
+            let (sin, cos) = angle.sin_cos();
+            let scale = cos.abs().max(sin.abs());
+            let mut x = (half_w + cos / scale * half_w).round() as usize;
+            let y = (half_h + sin / scale * half_h).round() as usize;
+            // Odd positions are blocks, move towards the center
+            if x % 2 == 1 && y % 2 == 1 {
+                if (x as f64) < half_w {
+                    x += 1;
+                } else {
+                    x -= 1;
+                }
+            }
+            positions.push((x, y));
+        }
+        positions
+    }
+
+    fn player_can_move_x_y<R: Rng>(&self, p: &MapPlayer, rng: &mut R) -> bool {
         let mut can_move_x = false;
         let mut can_move_y = false;
         let mut checked_pos = Vec::new();
         let mut to_check_pos = Vec::new();
 
         to_check_pos.push((p.x as usize, p.y as usize));
-        let mut offset : i32 = rand::thread_rng().gen();
+        let mut offset : i32 = rng.gen();
         offset = (offset % 4) * 90;
 
         while !to_check_pos.is_empty() {
@@ -166,6 +196,7 @@ impl Map {
                     speed_factor: 0,
                     bomb: 0,
                     dead: false,
+                    team: None,
                 };
 
                 let walkable_item = match &self.items[linearized_pos] {
@@ -195,12 +226,17 @@ impl Map {
         false
     }
 
-    fn get_square_non_walkable(&self, p: &MapPlayer) -> (i32, i32) {
+    /**
+     * Find an obstacle to remove near a player. Blocks are only removed when the
+     * player is enclosed by blocks
+     */
+    fn get_square_non_walkable<R: Rng>(&self, p: &MapPlayer, rng: &mut R) -> (i32, i32) {
+        let mut first_block = None;
         let mut checked_pos = Vec::new();
         let mut to_check_pos = Vec::new();
 
         to_check_pos.push((p.x as usize, p.y as usize));
-        let mut offset : i32 = rand::thread_rng().gen();
+        let mut offset : i32 = rng.gen();
         offset = (offset % 4) * 90;
 
         while !to_check_pos.is_empty() {
@@ -226,6 +262,7 @@ impl Map {
                     speed_factor: 0,
                     bomb: 0,
                     dead: false,
+                    team: None,
                 };
 
                 let walkable_item = match &self.items[linearized_pos] {
@@ -240,19 +277,21 @@ impl Map {
                     }
                 } else if self.squares[linearized_pos].sq_type != SquareType::Block {
                     return (x, y);
+                } else if first_block.is_none() {
+                    first_block = Some((x, y));
                 }
             }
         }
-        (-1, -1)
+        first_block.unwrap_or((-1, -1))
     }
 
     /**
      * Modify the map till all players can safely play
      */
-    fn make_startable(&mut self) {
+    fn make_startable<R: Rng>(&mut self, rng: &mut R) {
         for p in &self.players {
-            while !self.player_can_move_x_y(p) {
-                let pos = self.get_square_non_walkable(p);
+            while !self.player_can_move_x_y(p, rng) {
+                let pos = self.get_square_non_walkable(p, rng);
                 if pos.0 == -1 && pos.1 == -1 {
                     return;
                 }
@@ -326,4 +365,70 @@ impl fmt::Display for Map {
         }
         write!(f, "{}", map_str)
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Read a map printed by `impl Display for Map`. The alphabet loses the kind
+ * of items, so bonuses are read as MoreBombs, maluses as Slow and unknown
+ * items as empty squares. Players are numbered in reading order.
+ * Surrounding spaces and empty lines are ignored.
+ */
+impl FromStr for Map {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Map, String> {
+        let rows: Vec<&str> = s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+        if rows.is_empty() {
+            return Err(String::from("Empty map"));
+        }
+        let h = rows.len();
+        let w = rows[0].chars().count();
+        let mut squares = Vec::with_capacity(w * h);
+        let mut items: Vec<Option<InteractiveItem>> = Vec::with_capacity(w * h);
+        let mut players = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != w {
+                return Err(format!("Row {} has not the width of the map", y));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let sq_type = match c {
+                    'B' => SquareType::Block,
+                    'H' => SquareType::Water,
+                    'N' => SquareType::Wall(Direction::North),
+                    'S' => SquareType::Wall(Direction::South),
+                    'W' => SquareType::Wall(Direction::West),
+                    'E' => SquareType::Wall(Direction::East),
+                    _ => SquareType::Empty,
+                };
+                let item: Option<InteractiveItem> = match c {
+                    'D' => Some(Box::new(DestructibleBox {})),
+                    'O' => Some(Box::new(Bonus::MoreBombs)),
+                    'M' => Some(Box::new(Malus::Slow)),
+                    'b' | 'p' => Some(Box::new(BombItem {})),
+                    'X' | 'P' | 'u' | 'B' | 'H' | 'N' | 'S' | 'W' | 'E' => None,
+                    _ => return Err(format!("Unknown square '{}' at {},{}", c, x, y)),
+                };
+                squares.push(Square { sq_type });
+                items.push(item);
+                if c == 'P' || c == 'p' {
+                    players.push(MapPlayer {
+                        x: x as f32 + 0.5,
+                        y: y as f32 + 0.5,
+                        radius: 2,
+                        speed_factor: 1000,
+                        bomb: 1,
+                        dead: false,
+                        team: None,
+                    });
+                }
+            }
+        }
+        Ok(Map {
+            w,
+            h,
+            squares,
+            players,
+            items
+        })
+    }
+}
@@ -0,0 +1,248 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::analysis::MapAnalysis;
+use super::item::DropTable;
+use super::map::Map;
+use super::utils::{MapPlayer, SquareType};
+
+/**
+ * A hand-authored map. A map file is made of optional headers, a `---` line, then
+ * the map in the alphabet printed by `impl Display for Map`:
+ *
+ *   # Comment
+ *   name = Arena
+ *   size = 13x11
+ *   spawns = 0,0 12,0 0,10 12,10
+ *   drops = MoreBombs:2 Slow:1 Nothing:3
+ *   ---
+ *   XXDDDDDDDDDXX
+ *   ...
+ *
+ * Without a spawns header, players spawn on the P squares of the map.
+ * Without a drops header, the drop table of the room settings is used.
+ */
+#[derive(Clone, Debug)]
+pub struct MapFile {
+    pub name: String,
+    pub map: Map,
+    pub drops: Option<DropTable>,
+}
+
+fn parse_position(s: &str) -> Result<(usize, usize), String> {
+    let mut coords = s.splitn(2, ',').map(|c| c.trim().parse::<usize>());
+    match (coords.next(), coords.next()) {
+        (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+        _ => Err(format!("Invalid position {}", s)),
+    }
+}
+
+impl FromStr for MapFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MapFile, String> {
+        let separator = s.lines().position(|l| l.trim() == "---");
+        let (headers, grid) = match separator {
+            Some(idx) => {
+                let lines: Vec<&str> = s.lines().collect();
+                (lines[..idx].to_vec(), lines[(idx + 1)..].join("\n"))
+            },
+            None => (Vec::new(), String::from(s)),
+        };
+        let mut name = String::new();
+        let mut size = None;
+        let mut spawns = None;
+        let mut drops = None;
+        for line in headers {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let value = parts.next().ok_or_else(|| format!("Invalid header {}", line))?.trim();
+            match key {
+                "name" => name = String::from(value),
+                "size" => {
+                    let mut dims = value.splitn(2, 'x').map(|d| d.trim().parse::<usize>());
+                    size = match (dims.next(), dims.next()) {
+                        (Some(Ok(w)), Some(Ok(h))) => Some((w, h)),
+                        _ => return Err(format!("Invalid size {}", value)),
+                    };
+                },
+                "spawns" => {
+                    let positions: Result<Vec<(usize, usize)>, String> =
+                        value.split_whitespace().map(parse_position).collect();
+                    spawns = Some(positions?);
+                },
+                "drops" => drops = Some(value.parse::<DropTable>()?),
+                _ => return Err(format!("Unknown header {}", key)),
+            }
+        }
+
+        let mut map: Map = grid.parse()?;
+        if let Some((w, h)) = size {
+            if map.w != w || map.h != h {
+                return Err(format!("Map is {}x{}, not {}x{}", map.w, map.h, w, h));
+            }
+        }
+        if let Some(spawns) = spawns {
+            map.players.clear();
+            for (x, y) in spawns {
+                if x >= map.w || y >= map.h {
+                    return Err(format!("Spawn {},{} is out of the map", x, y));
+                }
+                map.players.push(MapPlayer {
+                    x: x as f32 + 0.5,
+                    y: y as f32 + 0.5,
+                    radius: 2,
+                    speed_factor: 1000,
+                    bomb: 1,
+                    dead: false,
+                    team: None,
+                });
+            }
+        }
+        for p in &map.players {
+            let idx = p.x as usize + p.y as usize * map.w;
+            if map.squares[idx].sq_type != SquareType::Empty || map.items[idx].is_some() {
+                return Err(format!("Spawn {},{} is not an empty square", p.x as usize, p.y as usize));
+            }
+        }
+        if map.players.len() < 2 {
+            return Err(String::from("A map needs at least 2 spawns"));
+        }
+        if let Some(drops) = &drops {
+            drops.validate()?;
+        }
+        Ok(MapFile {
+            name,
+            map,
+            drops,
+        })
+    }
+}
+
+impl fmt::Display for MapFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "name = {}", self.name)?;
+        writeln!(f, "size = {}x{}", self.map.w, self.map.h)?;
+        let spawns: Vec<String> = self.map.players.iter()
+            .map(|p| format!("{},{}", p.x as usize, p.y as usize))
+            .collect();
+        writeln!(f, "spawns = {}", spawns.join(" "))?;
+        if let Some(drops) = &self.drops {
+            writeln!(f, "drops = {}", drops)?;
+        }
+        writeln!(f, "---")?;
+        write!(f, "{}", self.map)
+    }
+}
+
+impl MapFile {
+    /**
+     * Read a map file
+     * @param path  The file to read
+     * @return      The map, or why it can't be read
+     */
+    pub fn load(path: &Path) -> Result<MapFile, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut file: MapFile = content.parse()?;
+        if file.name.is_empty() {
+            file.name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        }
+        Ok(file)
+    }
+}
+
+/**
+ * Custom maps played one after the other
+ */
+#[derive(Clone, Debug, Default)]
+pub struct MapRotation {
+    maps: Vec<MapFile>,
+    next: usize,
+}
+
+impl MapRotation {
+    /**
+     * Load every .map file of a directory, sorted by file name. Invalid files are skipped
+     * @param dir   The directory
+     * @return      The rotation
+     */
+    pub fn load(dir: &Path) -> MapRotation {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.extension().map_or(false, |ext| ext == "map"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        let mut maps = Vec::new();
+        for path in paths {
+            match MapFile::load(&path) {
+                Ok(file) => {
+                    // Custom maps are played anyway, the author may want them this way
+                    if !MapAnalysis::new(&file.map).is_fair() {
+                        warn!("Map {} may be unfair for some players", path.display());
+                    }
+                    maps.push(file);
+                },
+                Err(e) => warn!("Can't load map {}: {}", path.display(), e),
+            }
+        }
+        MapRotation {
+            maps,
+            next: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    /**
+     * Get the next map of the rotation with enough spawns
+     * @param players   Number of players to spawn
+     * @return          The map, None if no map can be played
+     */
+    pub fn next(&mut self, players: usize) -> Option<&MapFile> {
+        for offset in 0..self.maps.len() {
+            let idx = (self.next + offset) % self.maps.len();
+            if self.maps[idx].map.players.len() >= players {
+                self.next = idx + 1;
+                return Some(&self.maps[idx]);
+            }
+        }
+        None
+    }
+}
@@ -25,8 +25,15 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
+pub mod analysis;
+pub mod generator;
 pub mod map;
+pub mod mapfile;
 pub mod item;
 pub mod utils;
+#[cfg(test)]
+mod tests;
 
-pub use map::Map;
\ No newline at end of file
+pub use analysis::{MapAnalysis, SpawnMetrics};
+pub use map::Map;
+pub use mapfile::{MapFile, MapRotation};
\ No newline at end of file
@@ -0,0 +1,173 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::path::Path;
+
+use super::generator::{generator_by_name, MirroredGenerator};
+use super::item::{Bonus, Drop};
+use super::utils::SquareType;
+use super::{Map, MapAnalysis, MapFile};
+
+#[test]
+fn maps_are_read_back_from_their_display() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let map = Map::new(13, 11, 4, &mut rng);
+    let parsed: Map = format!("{}", map).parse().unwrap();
+    assert_eq!(format!("{}", parsed), format!("{}", map));
+    assert_eq!(parsed.squares, map.squares);
+    assert_eq!(parsed.players.len(), 4);
+}
+
+#[test]
+fn map_files_have_headers() {
+    let file: MapFile = "
+        # A tiny arena
+        name = Tiny
+        size = 3x2
+        spawns = 0,0 2,1
+        drops = MoreBombs:1 Nothing:2
+        ---
+        XDX
+        XBX
+    ".parse().unwrap();
+    assert_eq!(file.name, "Tiny");
+    assert_eq!((file.map.w, file.map.h), (3, 2));
+    assert_eq!(file.map.players.len(), 2);
+    assert_eq!((file.map.players[1].x, file.map.players[1].y), (2.5, 1.5));
+    let drops = file.drops.unwrap();
+    assert_eq!(drops.drops, vec![(Drop::Bonus(Bonus::MoreBombs), 1), (Drop::Nothing, 2)]);
+}
+
+#[test]
+fn invalid_map_files_are_rejected() {
+    assert!("size = 4x2\n---\nPXP\nXXX".parse::<MapFile>().is_err());
+    assert!("spawns = 1,1 0,0\n---\nXXX\nXBX".parse::<MapFile>().is_err());
+    assert!("---\nPXX\nXBX".parse::<MapFile>().is_err());
+    assert!("drops = Nothing:0\n---\nPXP".parse::<MapFile>().is_err());
+    assert!("drops = Nothing:4294967295 Slow:1\n---\nPXP".parse::<MapFile>().is_err());
+    assert!("PXP\nXZX".parse::<MapFile>().is_err());
+}
+
+#[test]
+fn example_maps_are_valid() {
+    let file = MapFile::load(Path::new("maps/arena.map")).unwrap();
+    assert_eq!(file.name, "Arena");
+    assert_eq!(file.map.players.len(), 4);
+    let reloaded: MapFile = format!("{}", file).parse().unwrap();
+    assert_eq!(reloaded.map.squares, file.map.squares);
+    assert_eq!(reloaded.drops, file.drops);
+}
+
+#[test]
+fn generators_make_startable_maps() {
+    for name in &["classic", "mirrored", "maze", "cave", "arena"] {
+        let generator = generator_by_name(name).unwrap();
+        assert_eq!(generator.name(), *name);
+        for players in 2..17 {
+            let mut rng = StdRng::seed_from_u64(players as u64);
+            let map = Map::generate(&*generator, 27, 23, players, &mut rng);
+            assert_eq!(map.squares.len(), 27 * 23);
+            assert_eq!(map.players.len(), players);
+            // Walls can be crossed from one side
+            let free = |x: usize, y: usize| {
+                let sq_type = map.squares[x + y * map.w].sq_type;
+                sq_type != SquareType::Block && sq_type != SquareType::Water && map.items[x + y * map.w].is_none()
+            };
+            for p in &map.players {
+                let (x, y) = (p.x as usize, p.y as usize);
+                assert_eq!(map.squares[x + y * map.w].sq_type, SquareType::Empty);
+                assert!(free(x, y), "{} spawns {} players on an obstacle", name, players);
+                let can_move = (x > 0 && free(x - 1, y)) || (x + 1 < map.w && free(x + 1, y))
+                    || (y > 0 && free(x, y - 1)) || (y + 1 < map.h && free(x, y + 1));
+                assert!(can_move, "{} blocks a player with {} players", name, players);
+            }
+        }
+    }
+}
+
+#[test]
+fn mirrored_maps_are_symmetric() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let map = Map::generate(&MirroredGenerator, 13, 11, 4, &mut rng);
+    for y in 0..map.h {
+        for x in 0..map.w {
+            let opposite = (map.w - 1 - x) + (map.h - 1 - y) * map.w;
+            let blocks = |idx: usize| map.squares[idx].sq_type == SquareType::Block;
+            assert_eq!(blocks(x + y * map.w), blocks(opposite));
+        }
+    }
+}
+
+
+#[test]
+fn spawns_are_analysed() {
+    let map: Map = "
+        PXDXP
+        XBBBX
+        XXXXX
+    ".parse().unwrap();
+    let analysis = MapAnalysis::new(&map);
+    for spawn in &analysis.spawns {
+        assert_eq!(spawn.reachable_area, 11);
+        assert_eq!(spawn.reachable_boxes, 1);
+        assert_eq!(spawn.nearest_box, Some(1));
+        assert_eq!(spawn.nearest_opponent, Some(4));
+    }
+    assert!(analysis.is_fair());
+
+    // The first player is stuck behind a box
+    let analysis = MapAnalysis::new(&"PDXXXXXXP".parse().unwrap());
+    assert_eq!(analysis.spawns[0].reachable_area, 1);
+    assert_eq!(analysis.spawns[1].reachable_area, 7);
+    assert!(analysis.balance() > 0.);
+    assert!(!analysis.is_fair());
+
+    // The players can't reach each other
+    let analysis = MapAnalysis::new(&"PXBXP".parse().unwrap());
+    assert_eq!(analysis.spawn_distances[0][1], None);
+    assert_eq!(analysis.balance(), 0.);
+    assert!(!analysis.is_fair());
+}
+
+#[test]
+fn generated_maps_are_fair() {
+    // The map grows with the number of players, as in GameSettings::map_size
+    let sizes = [(2, 13, 11), (4, 13, 11), (8, 19, 17), (16, 27, 23)];
+    for name in &["classic", "mirrored", "maze", "cave", "arena"] {
+        let generator = generator_by_name(name).unwrap();
+        for &(players, w, h) in &sizes {
+            for seed in 0..10 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let map = Map::generate(&*generator, w, h, players, &mut rng);
+                assert!(MapAnalysis::new(&map).is_fair(),
+                    "unfair {} map for {} players, seed {}", name, players, seed);
+            }
+        }
+    }
+}
@@ -25,15 +25,16 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
-use std::time::Instant;
+use std::time::Duration;
 use crate::bomber::gen::item::{Bonus, Malus};
 
 /**
  * Represent a player effet
+ * end is expressed in game time (see GameClock::now)
  */
 #[derive(Clone)]
 pub struct PlayerEffect {
-    pub end: Option<Instant>,
+    pub end: Option<Duration>,
     pub malus: Option<Malus>,
     pub bonus: Option<Bonus>,
 }
\ No newline at end of file
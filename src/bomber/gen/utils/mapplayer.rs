@@ -36,4 +36,5 @@ pub struct MapPlayer {
     pub speed_factor: u32,
     pub bomb: u32,
     pub dead: bool,
+    pub team: Option<u32>,
 }
\ No newline at end of file
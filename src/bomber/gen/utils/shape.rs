@@ -28,9 +28,78 @@
 /**
  * Used for Bombs for example
  */
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Shape {
     Cross,
     Square,
     Circle
+}
+
+impl Shape {
+    /**
+     * Get the squares reached by a shape at exactly a given radius
+     * @param r     The radius
+     * @return      The offsets from the center of the shape
+     */
+    pub fn ring(&self, r: i32) -> Vec<(i32, i32)> {
+        if r <= 0 {
+            return vec![(0, 0)];
+        }
+        match self {
+            Shape::Cross => vec![(r, 0), (0, r), (-r, 0), (0, -r)],
+            _ => {
+                let mut res = Vec::new();
+                for dy in -r..(r + 1) {
+                    for dx in -r..(r + 1) {
+                        if self.radius_of(&(dx, dy)) == Some(r) {
+                            res.push((dx, dy));
+                        }
+                    }
+                }
+                res
+            }
+        }
+    }
+
+    /**
+     * Get the radius at which an offset is reached by the shape
+     * @param offset    The offset from the center of the shape
+     * @return          The radius, None if the shape never reaches the offset
+     */
+    pub fn radius_of(&self, offset: &(i32, i32)) -> Option<i32> {
+        let (dx, dy) = (offset.0.abs(), offset.1.abs());
+        match self {
+            Shape::Cross => {
+                if dx == 0 || dy == 0 { Some(dx + dy) } else { None }
+            },
+            Shape::Square => Some(dx.max(dy)),
+            Shape::Circle => {
+                // Smallest r such as dx² + dy² <= r² + r
+                let d2 = dx * dx + dy * dy;
+                let mut r = dx.max(dy);
+                while r * r + r < d2 {
+                    r += 1;
+                }
+                Some(r)
+            }
+        }
+    }
+
+    /**
+     * Get the squares crossed from the center of the shape to an offset
+     * @param offset    The offset to reach
+     * @return          The crossed offsets, from the center (included) to offset (excluded)
+     */
+    pub fn path(offset: &(i32, i32)) -> Vec<(i32, i32)> {
+        let steps = offset.0.abs().max(offset.1.abs());
+        let mut res = Vec::new();
+        for step in 0..steps {
+            let ratio = step as f32 / steps as f32;
+            res.push((
+                (offset.0 as f32 * ratio).round() as i32,
+                (offset.1 as f32 * ratio).round() as i32,
+            ));
+        }
+        res
+    }
 }
\ No newline at end of file
@@ -45,7 +45,7 @@ impl Distribution<SquareType> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> SquareType {
         match rng.gen_range(0, 22) {
             0 => SquareType::Water,
-            1 => SquareType::Wall(rand::random()),
+            1 => SquareType::Wall(rng.gen()),
             _ => SquareType::Empty,
         }
     }
@@ -1,7 +1,8 @@
+use crate::bomber::core::game::PlayerStats;
 use crate::bomber::gen::item::InteractiveItem;
-use crate::bomber::gen::utils::SquareType;
-use rmps::Serializer;
-use serde::Serialize;
+use crate::bomber::gen::utils::{Shape, SquareType};
+
+// Events of a game, sent to players wrapped in a ServerMessage
 
 pub trait SerializedEvent {
     fn to_vec(&self) -> Vec<u8>;
@@ -9,139 +10,112 @@ pub trait SerializedEvent {
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct PlayerMove {
-    pub msg_type: String,
     pub id: i32,
     pub x: f32,
     pub y: f32
 }
 
-impl SerializedEvent for PlayerMove {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
-}
-
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct BombMove {
-    pub msg_type: String,
     pub old_x: f32,
     pub old_y: f32,
     pub x: f32,
     pub y: f32
 }
 
-impl SerializedEvent for BombMove {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BombPunched {
+    pub old_x: f32,
+    pub old_y: f32,
+    pub x: f32,
+    pub y: f32
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct PlayerPutBomb {
-    pub msg_type: String,
     pub id: i32,
     pub x: usize,
-    pub y: usize
-}
-
-impl SerializedEvent for PlayerPutBomb {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
+    pub y: usize,
+    pub shape: Shape,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct BombExplode {
-    pub msg_type: String,
     pub w: u64,
     pub h: u64,
 }
 
-impl SerializedEvent for BombExplode {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BombChain {
+    pub from_w: u64,
+    pub from_h: u64,
+    pub w: u64,
+    pub h: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct PlayerDie {
-    pub msg_type: String,
     pub id: u64,
+    pub killer: Option<u64>,
+    pub suicide: bool,
 }
 
-impl SerializedEvent for PlayerDie {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct GameOver {
+    pub winner: Option<u64>,
+    pub winning_team: Option<u32>,
+    pub draw: bool,
+    pub scoreboard: Vec<PlayerStats>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
-pub struct PlayerIdentity {
-    pub msg_type: String,
-    pub id: u64,
+pub struct RoundOver {
+    pub round: u32,
+    pub winner: Option<u64>,
+    pub winning_team: Option<u32>,
+    pub wins: Vec<u32>,
 }
 
-impl SerializedEvent for PlayerIdentity {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Intermission {
+    pub seconds: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Countdown {
+    pub seconds: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct MatchOver {
+    pub winner: Option<u64>,
+    pub winning_team: Option<u32>,
+    pub wins: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PlayerIdentity {
+    pub id: u64,
+    pub name: String,
+    pub team: Option<u32>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct CreateItem {
-    pub msg_type: String,
     pub item: Option<InteractiveItem>,
     pub w: u64,
     pub h: u64,
 }
 
-impl SerializedEvent for CreateItem {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
-}
-
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct UpdateSquare {
-    pub msg_type: String,
     pub square: SquareType,
     pub x: u64,
     pub y: u64,
 }
 
-impl SerializedEvent for UpdateSquare {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
-}
-
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct DestroyItem {
-    pub msg_type: String,
     pub w: u64,
     pub h: u64,
 }
-
-impl SerializedEvent for DestroyItem {
-    fn to_vec(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
-        buf
-    }
-}
\ No newline at end of file
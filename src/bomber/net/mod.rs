@@ -30,5 +30,8 @@ pub mod diff_msg;
 pub mod tlsserver;
 pub mod playerstreammanager;
 
+#[cfg(test)]
+mod tests;
+
 pub use tlsserver::{TlsServer, TlsServerConfig};
 pub use playerstreammanager::PlayerStreamManager;
\ No newline at end of file
@@ -25,117 +25,378 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use super::diff_msg::*;
 use super::super::gen::utils::Direction;
 use super::super::gen::map::Map;
+use super::super::core::game::{Difficulty, GameSettings};
+
+use rmps::Serializer;
+use serde::Serialize;
 
 // This file contains messages which will be wrapped via msgpack.
-// Each messages MUST have a unique msg_type.
+// Each message is an array starting with its msg_type, followed by its fields.
 
 /**
- * Generic message
+ * Messages sent by the clients
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct Msg {
-    pub msg_type: String, // TODO enum
+#[serde(tag = "msg_type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /* First message of a connection */
+    Hello { version: u32, features: Vec<String> },
+    /* Player details such as the name, its key, etc. */
+    Player { name: String },
+    Resume { token: String },
+    ListRooms,
+    Create {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
+        private: bool,          /* only joinable with the invite code */
+    },
+    Join {
+        room: String,           /* invite code */
+        #[serde(default)]
+        password: Option<String>,
+    },
+    Leave,
+    Teams { teams: u32, friendly_fire: bool },
+    Team { team: u32 },
+    Settings { settings: GameSettings },
+    AddBot { difficulty: Difficulty },
+    RemoveBot { id: u64 },
+    Kick { id: u64 },
+    Ready { ready: bool },
+    Launch,
+    Bomb,
+    Move { direction: Direction },
+    Punch { direction: Direction },
 }
 
-impl Msg {
-    pub fn new(msg_type: String) -> Msg {
-        Msg {
-            msg_type
+impl ClientMessage {
+    /**
+     * @return if the message is an action in the game
+     */
+    pub fn is_action(&self) -> bool {
+        match self {
+            ClientMessage::Bomb | ClientMessage::Move { .. } | ClientMessage::Punch { .. } => true,
+            _ => false,
         }
     }
 }
 
 /**
- * Message to send player details such as the name, its key, etc.
+ * Messages sent by the server
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct PlayerMsg {
-    pub msg_type: String,
-    pub name: String,
+#[serde(tag = "msg_type")]
+pub enum ServerMessage {
+    #[serde(rename = "error")]
+    Error(ErrorMsg),
+    #[serde(rename = "welcome")]
+    Welcome(WelcomeMsg),
+    #[serde(rename = "session")]
+    Session(SessionMsg),
+    #[serde(rename = "joined")]
+    Joined(JoinedMsg),
+    #[serde(rename = "rooms")]
+    Rooms(RoomsMsg),
+    #[serde(rename = "room_created")]
+    RoomCreated(RoomInfo),
+    #[serde(rename = "room_updated")]
+    RoomUpdated(RoomInfo),
+    #[serde(rename = "room_closed")]
+    RoomClosed(RoomClosedMsg),
+    #[serde(rename = "settings")]
+    Settings(SettingsMsg),
+    #[serde(rename = "kicked")]
+    Kicked(KickedMsg),
+    #[serde(rename = "room_players")]
+    RoomPlayers(RoomPlayersMsg),
+    #[serde(rename = "room_teams")]
+    RoomTeams(RoomTeamsMsg),
+    #[serde(rename = "map")]
+    Map(MapMsg),
+    #[serde(rename = "player_identity")]
+    PlayerIdentity(PlayerIdentity),
+    #[serde(rename = "player_move_diff")]
+    PlayerMove(PlayerMove),
+    #[serde(rename = "bomb_move_diff")]
+    BombMove(BombMove),
+    #[serde(rename = "bomb_punched")]
+    BombPunched(BombPunched),
+    #[serde(rename = "player_put_bomb_diff")]
+    PlayerPutBomb(PlayerPutBomb),
+    #[serde(rename = "bomb_explode")]
+    BombExplode(BombExplode),
+    #[serde(rename = "bomb_chain")]
+    BombChain(BombChain),
+    #[serde(rename = "player_die")]
+    PlayerDie(PlayerDie),
+    #[serde(rename = "create_item")]
+    CreateItem(CreateItem),
+    #[serde(rename = "update_square")]
+    UpdateSquare(UpdateSquare),
+    #[serde(rename = "destroy_item")]
+    DestroyItem(DestroyItem),
+    #[serde(rename = "game_over")]
+    GameOver(GameOver),
+    #[serde(rename = "round_over")]
+    RoundOver(RoundOver),
+    #[serde(rename = "intermission")]
+    Intermission(Intermission),
+    #[serde(rename = "countdown")]
+    Countdown(Countdown),
+    #[serde(rename = "match_over")]
+    MatchOver(MatchOver),
+}
+
+impl SerializedEvent for ServerMessage {
+    fn to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        buf
+    }
 }
 
-impl PlayerMsg {
-    pub fn new(name: String) -> PlayerMsg {
-        PlayerMsg {
-            name,
-            msg_type: String::from("player")
+/**
+ * Message when a request of a player can't be processed
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ErrorMsg {
+    pub reason: String,
+}
+
+impl ErrorMsg {
+    pub fn new(reason: String) -> ErrorMsg {
+        ErrorMsg {
+            reason,
         }
     }
 }
 
 /**
- * Message to join a room
+ * Answer to the hello of a client, with the features enabled for the connection
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct JoinMsg {
-    pub msg_type: String,
-    pub room: u64,
+pub struct WelcomeMsg {
+    pub version: u32,
+    pub server: String,
+    pub features: Vec<String>,
 }
 
-impl JoinMsg {
-    pub fn new(room: u64) -> JoinMsg {
-        JoinMsg {
-            room,
-            msg_type: String::from("join")
+impl WelcomeMsg {
+    pub fn new(version: u32, features: Vec<String>) -> WelcomeMsg {
+        WelcomeMsg {
+            version,
+            server: format!("bomberust-server {}", env!("CARGO_PKG_VERSION")),
+            features,
         }
     }
 }
 
 /**
- * Message to move a player
+ * Message sent on connection with the token to resume the session later
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct MoveMsg {
-    pub msg_type: String,
-    pub direction: Direction,
+pub struct SessionMsg {
+    pub id: u64,
+    pub token: String,
 }
 
-impl MoveMsg {
-    pub fn new(direction: Direction) -> MoveMsg {
-        MoveMsg {
-            msg_type: String::from("move"),
-            direction,
+impl SessionMsg {
+    pub fn new(id: u64, token: String) -> SessionMsg {
+        SessionMsg {
+            id,
+            token,
         }
     }
 }
 
 /**
- * Message to move a player
+ * Message to send the map of a game
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct MapMsg {
-    pub msg_type: String,
     pub map: Map,
 }
 
 impl MapMsg {
     pub fn new(map: Map) -> MapMsg {
         MapMsg {
-            msg_type: String::from("map"),
             map,
         }
     }
 }
 
 /**
- * Message when a player join a room or lobby (room 0)
+ * Message when the settings of the room change
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct SettingsMsg {
+    pub settings: GameSettings,
+}
+
+impl SettingsMsg {
+    pub fn new(settings: GameSettings) -> SettingsMsg {
+        SettingsMsg {
+            settings,
+        }
+    }
+}
+
+/**
+ * Message when the owner of a room kicks the player. The player is back in the lobby
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct KickedMsg {
+    pub room: String,
+}
+
+impl KickedMsg {
+    pub fn new(room: String) -> KickedMsg {
+        KickedMsg {
+            room,
+        }
+    }
+}
+
+/**
+ * A member of a room
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomPlayer {
+    pub id: u64,
+    pub name: String,
+    pub bot: bool,
+    pub owner: bool,        /* can configure, launch and kick */
+    pub ready: bool,
+    pub gid: Option<u64>,   /* id in the game, once launched */
+}
+
+/**
+ * Message when members of a room change
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomPlayersMsg {
+    pub players: Vec<RoomPlayer>,
+}
+
+impl RoomPlayersMsg {
+    pub fn new(players: Vec<RoomPlayer>) -> RoomPlayersMsg {
+        RoomPlayersMsg {
+            players,
+        }
+    }
+}
+
+/**
+ * Message when teams of a room change
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomTeamsMsg {
+    pub teams: u32,
+    pub friendly_fire: bool,
+    pub players: HashMap<u64, u32>,
+}
+
+impl RoomTeamsMsg {
+    pub fn new(teams: u32, friendly_fire: bool, players: HashMap<u64, u32>) -> RoomTeamsMsg {
+        RoomTeamsMsg {
+            teams,
+            friendly_fire,
+            players,
+        }
+    }
+}
+
+/**
+ * Why a player can't join a room
+ */
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum JoinError {
+    NotFound,
+    AlreadyInRoom,
+    WrongPassword,
+    Running,
+    Full,
+}
+
+/**
+ * Message when a player join a room or lobby (None)
  */
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct JoinedMsg {
-    pub msg_type: String,
-    pub room: u64,
-    pub success: bool
+    pub room: Option<String>,       /* invite code */
+    pub success: bool,
+    pub reason: Option<JoinError>,
 }
 
 impl JoinedMsg {
-    pub fn new(room: u64, success: bool) -> JoinedMsg {
+    pub fn new(room: Option<String>, success: bool) -> JoinedMsg {
         JoinedMsg {
             room,
             success,
-            msg_type: String::from("joined")
+            reason: None,
+        }
+    }
+
+    pub fn refused(room: Option<String>, reason: JoinError) -> JoinedMsg {
+        JoinedMsg {
+            room,
+            success: false,
+            reason: Some(reason),
+        }
+    }
+}
+
+/**
+ * Description of a room, for players looking for a game
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomInfo {
+    pub code: String,       /* invite code */
+    pub name: String,
+    pub owner: String,
+    pub players: u32,       /* bots included */
+    pub capacity: u32,
+    pub running: bool,
+    pub generator: String,
+    pub teams: u32,
+    pub rounds_to_win: u32,
+}
+
+/**
+ * Answer to list_rooms
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomsMsg {
+    pub rooms: Vec<RoomInfo>,
+}
+
+impl RoomsMsg {
+    pub fn new(rooms: Vec<RoomInfo>) -> RoomsMsg {
+        RoomsMsg {
+            rooms,
         }
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Message to the lobby when a room is removed
+ */
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct RoomClosedMsg {
+    pub code: String,
+}
+
+impl RoomClosedMsg {
+    pub fn new(code: String) -> RoomClosedMsg {
+        RoomClosedMsg {
+            code,
+        }
+    }
+}
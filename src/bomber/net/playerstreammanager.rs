@@ -25,17 +25,24 @@
  * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  **/
 
+use super::diff_msg::SerializedEvent;
 use super::msg::*;
-use super::super::core::Server;
-use super::super::gen::utils::Direction;
+use super::super::core::{RoomOptions, Server};
 
-use rmps::{ Serializer, Deserializer };
-use rmps::decode::Error;
-use serde::{ Serialize, Deserialize };
+use rmps::Deserializer;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::sync::{ Arc, Mutex };
 use std::time::{ Duration, Instant };
 
+// Version of the protocol, increased on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+// Oldest version of the protocol supported by the server
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+// Optional features a client can ask for
+pub const SERVER_FEATURES: [&str; 3] = ["resume", "bots", "teams"];
+
 /**
  * Contains the current datas
  */
@@ -49,10 +56,11 @@ pub struct RtpBuf {
  * Wrap the TLS socket and current incoming datas
  */
 pub struct Stream {
-    id: u64,
     pub data: Arc<Mutex<Option<Vec<u8>>>>,
     rtp_buf: RtpBuf,
     last_pkt: Instant,
+    features: Option<Vec<String>>,  /* None until the handshake is done */
+    closed: bool,
 }
 
 /**
@@ -60,7 +68,7 @@ pub struct Stream {
  */
 pub struct PlayerStreamManager {
     current_id: u64,
-    pub streams: Vec<Stream>,
+    pub streams: HashMap<u64, Stream>,
     pub server: Arc<Mutex<Server>>,
 }
 
@@ -71,37 +79,84 @@ impl PlayerStreamManager {
     pub fn new(server: Arc<Mutex<Server>>) -> PlayerStreamManager {
         PlayerStreamManager {
             current_id: 0,
-            streams: Vec::new(),
+            streams: HashMap::new(),
             server,
         }
     }
 
     /**
-     * Add a stream to process
+     * Add a stream to process. The player joins the lobby after the handshake
      * @param stream    The stream to add
      * @return          The stream id
      */
     pub fn add_stream(&mut self) -> u64 {
         let id = self.current_id;
-        let data = Arc::new(Mutex::new(None));
-        self.streams.push(Stream {
-            id,
-            data: data.clone(),
+        self.streams.insert(id, Stream {
+            data: Arc::new(Mutex::new(None)),
             rtp_buf: RtpBuf {
                 data: [0; 65536],
                 size: 0,
                 wanted: 0,
             },
-            last_pkt: Instant::now()
+            last_pkt: Instant::now(),
+            features: None,
+            closed: false,
         });
         self.current_id += 1;
-        self.server.lock().unwrap().join_server(id, data);
         id
     }
 
+    /**
+     * First message of a stream, check that the client is compatible
+     * @param id        The stream id
+     * @param version   The protocol version of the client
+     * @param features  The features wanted by the client
+     * @return          If the client is accepted
+     */
+    fn handshake(&mut self, id: u64, version: u32, features: Vec<String>) -> bool {
+        if version < MIN_PROTOCOL_VERSION || version > PROTOCOL_VERSION {
+            warn!("Client ({}) uses an incompatible protocol version {}", id, version);
+            let reason = format!("Incompatible protocol version {}, the server supports versions {} to {}",
+                version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION);
+            self.close(id, reason);
+            return false;
+        }
+        let features: Vec<String> = features.into_iter()
+            .filter(|f| SERVER_FEATURES.contains(&f.as_str()))
+            .collect();
+        match self.streams.get_mut(&id) {
+            Some(stream) => stream.features = Some(features.clone()),
+            None => return false,
+        }
+        self.send(id, ServerMessage::Welcome(WelcomeMsg::new(PROTOCOL_VERSION, features)));
+        self.server.lock().unwrap().join_server(id);
+        true
+    }
+
+    /**
+     * Send an error and close a stream
+     * @param id        The stream id
+     * @param reason    The error to send
+     */
+    fn close(&mut self, id: u64, reason: String) {
+        self.send(id, ServerMessage::Error(ErrorMsg::new(reason)));
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.closed = true;
+        }
+    }
+
+    /**
+     * @param id    The stream id
+     * @return      If the stream must be closed once its data is sent
+     */
+    pub fn is_closed(&self, id: u64) -> bool {
+        self.streams.get(&id).map_or(true, |s| s.closed)
+    }
+
     /**
      * Each packets are wrapped in a msgpack object.
      * This function deserialize the message and execute the action.
+     * Invalid messages are answered with an error.
      * @note: TODO verify signature
      * @param pkt   The packet to process
      * @param id    The stream id
@@ -110,91 +165,171 @@ impl PlayerStreamManager {
         debug!("rx:{}", pkt.len());
         let cur = Cursor::new(&*pkt);
         let mut de = Deserializer::new(cur);
-        let actual: Result<Msg, Error> = Deserialize::deserialize(&mut de);
-        if actual.is_ok() {
-            let msg_type = actual.unwrap().msg_type;
-            let cur = Cursor::new(&*pkt);
-            let mut de = Deserializer::new(cur);
-            if msg_type == "create" {
-                let new_room_id = self.server.lock().unwrap().create_room(id);
-                if new_room_id != 0 {
-                    // Announce to player that they join the room
-                    let mut buf = Vec::new();
-                    let msg = JoinedMsg::new(new_room_id, true);
-                    msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-                    let len = buf.len() as u16;
-                    let mut send_buf : Vec<u8> = Vec::with_capacity(65536);
-                    send_buf.push((len >> 8) as u8);
-                    send_buf.push((len as u16 % (2 as u16).pow(8)) as u8);
-                    send_buf.append(&mut buf);
-
-                    for stream in &self.streams {
-                        if stream.id == id {
-                            *stream.data.lock().unwrap() = Some(send_buf.clone());
-                            break;
-                        }
-                    }
-                }
-            } else if msg_type == "leave" {
-                let success = self.server.lock().unwrap().leave_room(id);
-                // Announce to player that they join the room
-                let mut buf = Vec::new();
-                let msg = JoinedMsg::new(0, success);
-                msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-                let len = buf.len() as u16;
-                let mut send_buf : Vec<u8> = Vec::with_capacity(65536);
-                send_buf.push((len >> 8) as u8);
-                send_buf.push((len as u16 % (2 as u16).pow(8)) as u8);
-                send_buf.append(&mut buf);
-
-                for stream in &self.streams {
-                    if stream.id == id {
-                        *stream.data.lock().unwrap() = Some(send_buf.clone());
-                        break;
-                    }
+        let msg: ClientMessage = match Deserialize::deserialize(&mut de) {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("Invalid message from ({}): {}", id, e);
+                self.send(id, ServerMessage::Error(ErrorMsg::new(format!("Invalid message: {}", e))));
+                return;
+            }
+        };
+        let connected = self.streams.get(&id).map_or(false, |s| s.features.is_some());
+        if let ClientMessage::Hello { version, features } = msg {
+            if connected {
+                self.send(id, ServerMessage::Error(ErrorMsg::new(String::from("Handshake already done"))));
+            } else {
+                self.handshake(id, version, features);
+            }
+            return;
+        }
+        if !connected {
+            warn!("Client ({}) didn't start with a handshake", id);
+            self.close(id, String::from("The first message must be a hello"));
+            return;
+        }
+        if msg.is_action() {
+            if let Some(s) = self.streams.get_mut(&id) {
+                // Anti flood a packet have 10 ms delay.
+                if s.last_pkt + Duration::from_millis(10) > Instant::now() {
+                    return;
+                } else {
+                    s.last_pkt = Instant::now()
                 }
-            } else if msg_type == "join" {
-                let msg: JoinMsg = Deserialize::deserialize(&mut de).unwrap_or(JoinMsg::new(0));
-                let success = self.server.lock().unwrap().join_room(id, msg.room);
-                // Announce to player that they join the room
-                let mut buf = Vec::new();
-                let msg = JoinedMsg::new(id, success);
-                msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-                let len = buf.len() as u16;
-                let mut send_buf : Vec<u8> = Vec::with_capacity(65536);
-                send_buf.push((len >> 8) as u8);
-                send_buf.push((len as u16 % (2 as u16).pow(8)) as u8);
-                send_buf.append(&mut buf);
-
-                for stream in &self.streams {
-                    if stream.id == id {
-                        *stream.data.lock().unwrap() = Some(send_buf.clone());
-                        break;
-                    }
+            }
+        }
+        match msg {
+            ClientMessage::Hello { .. } => {},
+            ClientMessage::Player { name } => {
+                let result = self.server.lock().unwrap().set_name(id, &name);
+                if let Err(reason) = result {
+                    self.send(id, ServerMessage::Error(ErrorMsg::new(reason)));
                 }
-            } else if msg_type == "launch" {
-                self.server.lock().unwrap().launch_game(id);
-            } else {
-                // In game action
-                for s in &mut self.streams {
-                    // Anti flood a packet have 10 ms delay.
-                    if s.id == id {
-                        if s.last_pkt + Duration::from_millis(10) > Instant::now() {
-                            return;
-                        } else {
-                            s.last_pkt = Instant::now()
-                        }
+            },
+            ClientMessage::Resume { token } => {
+                let joined = {
+                    let mut server = self.server.lock().unwrap();
+                    match server.resume(id, &token) {
+                        Some(room) => JoinedMsg::new(server.room_code(room), true),
+                        None => JoinedMsg::new(None, false),
                     }
+                };
+                // Announce to player the room they are back in
+                self.send(id, ServerMessage::Joined(joined));
+            },
+            ClientMessage::ListRooms => {
+                let rooms = self.server.lock().unwrap().list_rooms();
+                self.send(id, ServerMessage::Rooms(RoomsMsg::new(rooms)));
+            },
+            ClientMessage::Create { name, password, private } => {
+                let options = RoomOptions { name, password, private };
+                let code = {
+                    let mut server = self.server.lock().unwrap();
+                    let new_room_id = server.create_room(id, options);
+                    server.room_code(new_room_id)
+                };
+                if code.is_some() {
+                    // Announce to player that they join the room
+                    self.send(id, ServerMessage::Joined(JoinedMsg::new(code, true)));
                 }
-                if msg_type == "bomb" {
-                    self.server.lock().unwrap().put_bomb(id);
-                } else if msg_type == "move" {
-                    let msg: MoveMsg = Deserialize::deserialize(&mut de).unwrap_or(MoveMsg::new(Direction::North));
-                    self.server.lock().unwrap().move_player(id, msg.direction);
+            },
+            ClientMessage::Join { room, password } => {
+                let result = self.server.lock().unwrap().join_room(id, &room, password.as_deref());
+                // Announce to player that they join the room, or why they can't
+                let joined = match result {
+                    Ok(()) => JoinedMsg::new(Some(room), true),
+                    Err(reason) => JoinedMsg::refused(Some(room), reason),
+                };
+                self.send(id, ServerMessage::Joined(joined));
+            },
+            ClientMessage::Leave => {
+                let success = self.server.lock().unwrap().leave_room(id);
+                // Announce to player that they join the lobby
+                self.send(id, ServerMessage::Joined(JoinedMsg::new(None, success)));
+            },
+            ClientMessage::Teams { teams, friendly_fire } => {
+                self.server.lock().unwrap().set_teams(id, teams, friendly_fire);
+            },
+            ClientMessage::Team { team } => {
+                self.server.lock().unwrap().choose_team(id, team);
+            },
+            ClientMessage::Settings { settings } => {
+                let result = self.server.lock().unwrap().set_settings(id, settings);
+                if let Err(reason) = result {
+                    self.send(id, ServerMessage::Error(ErrorMsg::new(reason)));
                 }
+            },
+            ClientMessage::AddBot { difficulty } => {
+                self.server.lock().unwrap().add_bot(id, difficulty);
+            },
+            ClientMessage::RemoveBot { id: bot_id } => {
+                self.server.lock().unwrap().remove_bot(id, bot_id);
+            },
+            ClientMessage::Kick { id: kicked } => {
+                self.server.lock().unwrap().kick_player(id, kicked);
+            },
+            ClientMessage::Ready { ready } => {
+                self.server.lock().unwrap().set_ready(id, ready);
+            },
+            ClientMessage::Launch => {
+                self.server.lock().unwrap().launch_game(id);
+            },
+            ClientMessage::Bomb => {
+                self.server.lock().unwrap().put_bomb(id);
+            },
+            ClientMessage::Move { direction } => {
+                self.server.lock().unwrap().move_player(id, direction);
+            },
+            ClientMessage::Punch { direction } => {
+                self.server.lock().unwrap().punch_bomb(id, direction);
+            },
+        }
+    }
+
+    /**
+     * Send a message to a stream
+     * @param id    The stream id
+     * @param msg   The message to send
+     */
+    fn send(&self, id: u64, msg: ServerMessage) {
+        let mut buf = msg.to_vec();
+        let len = buf.len() as u16;
+        let mut send_buf : Vec<u8> = Vec::with_capacity(65536);
+        send_buf.push((len >> 8) as u8);
+        send_buf.push((len as u16 % (2 as u16).pow(8)) as u8);
+        send_buf.append(&mut buf);
+
+        if let Some(stream) = self.streams.get(&id) {
+            // Keep the messages not written yet
+            let mut data = stream.data.lock().unwrap();
+            match data.as_mut() {
+                Some(pending) => pending.append(&mut send_buf),
+                None => *data = Some(send_buf),
             }
         }
     }
+    /**
+     * The connection of a stream dropped. The stream is removed, the player
+     * is removed from the server if it doesn't reconnect in time
+     * @param id    The stream id
+     */
+    pub fn disconnect_stream(&mut self, id: u64) {
+        self.streams.remove(&id);
+        self.server.lock().unwrap().disconnect(id);
+    }
+
+    /**
+     * Remove players which didn't reconnect in time
+     */
+    pub fn remove_expired_sessions(&mut self) {
+        self.server.lock().unwrap().remove_expired_sessions();
+    }
+
+    /**
+     * Tell the lobby about the matches which ended
+     */
+    pub fn update_finished_matches(&mut self) {
+        self.server.lock().unwrap().update_finished_matches();
+    }
 
     pub fn get_events(&mut self, id: u64) -> Vec<Vec<u8>> {
         self.server.lock().unwrap().get_events(&id)
@@ -207,7 +342,10 @@ impl PlayerStreamManager {
      */
     pub fn process_stream(&mut self, id: u64, buf: &Vec<u8>) {
         let mut pkts: Vec<Vec<u8>> = Vec::new();
-        let rtp_buf = &mut self.streams[id as usize].rtp_buf;
+        let rtp_buf = match self.streams.get_mut(&id) {
+            Some(stream) => &mut stream.rtp_buf,
+            None => return,
+        };
         let size = buf.len() as u16;
         let mut parsed = 0;
         loop {
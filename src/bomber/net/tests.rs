@@ -0,0 +1,165 @@
+/**
+ * Copyright (c) 2019, Sébastien Blin <sebastien.blin@enconn.fr>
+ * All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * * Redistributions of source code must retain the above copyright
+ *  notice, this list of conditions and the following disclaimer.
+ * * Redistributions in binary form must reproduce the above copyright
+ *  notice, this list of conditions and the following disclaimer in the
+ *  documentation and/or other materials provided with the distribution.
+ * * Neither the name of the University of California, Berkeley nor the
+ *  names of its contributors may be used to endorse or promote products
+ *  derived from this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE REGENTS AND CONTRIBUTORS ``AS IS'' AND ANY
+ * EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE REGENTS AND CONTRIBUTORS BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES
+ * (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES;
+ * LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND
+ * ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+ * (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+ * SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ **/
+
+use rmps::{Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use super::PlayerStreamManager;
+use super::playerstreammanager::PROTOCOL_VERSION;
+use super::diff_msg::{PlayerDie, SerializedEvent};
+use super::msg::{ClientMessage, ErrorMsg, ServerMessage};
+use crate::bomber::core::Server;
+use crate::bomber::core::game::GameSettings;
+use crate::bomber::gen::utils::Direction;
+
+fn encode<T: Serialize>(msg: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
+    buf
+}
+
+fn decode<'a, T: Deserialize<'a>>(pkt: &'a [u8]) -> Result<T, rmps::decode::Error> {
+    Deserialize::deserialize(&mut Deserializer::new(Cursor::new(pkt)))
+}
+
+/**
+ * Frame a packet like the clients do
+ */
+fn frame(mut pkt: Vec<u8>) -> Vec<u8> {
+    let mut buf = vec![(pkt.len() >> 8) as u8, pkt.len() as u8];
+    buf.append(&mut pkt);
+    buf
+}
+
+#[test]
+fn messages_start_with_their_type() {
+    // Clients send arrays starting with the type of the message
+    let pkt = encode(&("move", Direction::West));
+    assert_eq!(decode::<ClientMessage>(&pkt).unwrap(), ClientMessage::Move { direction: Direction::West });
+    assert_eq!(decode::<ClientMessage>(&encode(&("launch",))).unwrap(), ClientMessage::Launch);
+    assert_eq!(decode::<ClientMessage>(&encode(&("create",))).unwrap(), ClientMessage::Create { name: None, password: None, private: false });
+    assert!(decode::<ClientMessage>(&encode(&("fly",))).is_err());
+    assert!(decode::<ClientMessage>(&encode(&("join", 1))).is_err());
+    assert_eq!(
+        decode::<ClientMessage>(&encode(&("join", "ABCD2345"))).unwrap(),
+        ClientMessage::Join { room: String::from("ABCD2345"), password: None }
+    );
+
+    let diff = ServerMessage::PlayerDie(PlayerDie { id: 1, killer: None, suicide: false });
+    assert_eq!(diff.to_vec(), encode(&("player_die", 1, None::<u64>, false)));
+    assert_eq!(decode::<ServerMessage>(&diff.to_vec()).unwrap(), diff);
+}
+
+/**
+ * Decode the messages waiting to be written on a stream
+ */
+fn sent(manager: &PlayerStreamManager, id: u64) -> Vec<ServerMessage> {
+    let data = manager.streams[&id].data.lock().unwrap().take().unwrap_or_default();
+    let mut msgs = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = ((data[pos] as usize) << 8) + data[pos + 1] as usize;
+        msgs.push(decode(&data[pos + 2..pos + 2 + len]).unwrap());
+        pos += 2 + len;
+    }
+    msgs
+}
+
+fn connect(manager: &mut PlayerStreamManager, version: u32) -> u64 {
+    let id = manager.add_stream();
+    manager.process_stream(id, &frame(encode(&("hello", version, vec!["resume", "compression"]))));
+    id
+}
+
+#[test]
+fn clients_start_with_a_handshake() {
+    let server = Arc::new(Mutex::new(Server::new()));
+    let mut manager = PlayerStreamManager::new(server);
+
+    let id = connect(&mut manager, PROTOCOL_VERSION);
+    match &sent(&manager, id)[..] {
+        [ServerMessage::Welcome(welcome)] => {
+            assert_eq!(welcome.version, PROTOCOL_VERSION);
+            assert_eq!(welcome.features, vec![String::from("resume")]);
+        },
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    assert!(!manager.is_closed(id));
+    // The player is in the lobby
+    match &manager.get_events(id)[..] {
+        [pkt] => assert!(matches!(decode(pkt).unwrap(), ServerMessage::Session(_))),
+        pkts => panic!("Unexpected events {:?}", pkts),
+    }
+
+    let id = connect(&mut manager, PROTOCOL_VERSION + 1);
+    match &sent(&manager, id)[..] {
+        [ServerMessage::Error(ErrorMsg { reason })] => assert!(reason.contains("version")),
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    assert!(manager.is_closed(id));
+    assert!(manager.get_events(id).is_empty());
+
+    let id = manager.add_stream();
+    manager.process_stream(id, &frame(encode(&("create",))));
+    assert!(matches!(sent(&manager, id)[..], [ServerMessage::Error(_)]));
+    assert!(manager.is_closed(id));
+}
+
+#[test]
+fn invalid_messages_are_answered() {
+    let server = Arc::new(Mutex::new(Server::new()));
+    let mut manager = PlayerStreamManager::new(server);
+    let id = connect(&mut manager, PROTOCOL_VERSION);
+    sent(&manager, id);
+    manager.process_stream(id, &frame(encode(&("fly",))));
+
+    match &sent(&manager, id)[..] {
+        [ServerMessage::Error(ErrorMsg { reason })] => assert!(reason.contains("fly")),
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    assert!(!manager.is_closed(id));
+}
+
+#[test]
+fn invalid_settings_are_answered() {
+    let server = Arc::new(Mutex::new(Server::new()));
+    let mut manager = PlayerStreamManager::new(server);
+    let id = connect(&mut manager, PROTOCOL_VERSION);
+    manager.process_stream(id, &frame(encode(&("create",))));
+    sent(&manager, id);
+
+    let settings = GameSettings { sudden_death: 0, ..Default::default() };
+    manager.process_stream(id, &frame(encode(&("settings", settings))));
+    match &sent(&manager, id)[..] {
+        [ServerMessage::Error(ErrorMsg { reason })] => assert!(reason.contains("Sudden death")),
+        msgs => panic!("Unexpected messages {:?}", msgs),
+    }
+    manager.process_stream(id, &frame(encode(&("settings", GameSettings::default()))));
+    assert!(sent(&manager, id).is_empty());
+}
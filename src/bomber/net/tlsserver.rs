@@ -79,6 +79,7 @@ impl TlsServer {
         let acceptor = TlsAcceptor::from(Arc::new(server_config));
         let socket = TcpListener::bind(&addr).unwrap();
         let stm = config.streams_manager.clone();
+        let stm_cleanup = config.streams_manager.clone();
         let done = socket.incoming()
             .for_each(move |stream| {
                 let addr = stream.peer_addr().ok();
@@ -87,7 +88,8 @@ impl TlsServer {
                 .and_then(move |stream| {
 
                     let id = stm.lock().unwrap().add_stream();
-                    let id_cloned = id.clone() as usize;
+                    let id_cloned = id.clone();
+                    let stm_disconnect = stm.clone();
 
                     // TODO Framed buffer for RTP packets?
                     let (mut rx, mut tx) = stream.split();
@@ -99,19 +101,24 @@ impl TlsServer {
                     })
                     .for_each(move |_| {
                         // TODO: Remove this as we have get events
-                        if stm.lock().unwrap().streams[id_cloned].data.lock().unwrap().is_some() {
+                        if stm.lock().unwrap().streams[&id_cloned].data.lock().unwrap().is_some() {
                             *connected_cln.lock().unwrap() = tx.poll_write(
-                                &*stm.lock().unwrap().streams[id_cloned].data
+                                &*stm.lock().unwrap().streams[&id_cloned].data
                                 .lock().as_ref().unwrap().as_ref().unwrap()
                             ).is_ok();
-                            *stm.lock().unwrap().streams[id_cloned].data.lock().unwrap() = None;
+                            *stm.lock().unwrap().streams[&id_cloned].data.lock().unwrap() = None;
+                        }
+
+                        // Rejected clients are disconnected once they got the reason
+                        if stm.lock().unwrap().is_closed(id_cloned) {
+                            *connected_cln.lock().unwrap() = false;
                         }
 
                         if !*connected_cln.lock().unwrap() {
                             return Ok(());
                         }
 
-                        let pkts = stm.lock().unwrap().get_events(id_cloned as u64);
+                        let pkts = stm.lock().unwrap().get_events(id_cloned);
                         for mut pkt in pkts {
                             let len = pkt.len() as u16;
                             let mut buf : Vec<u8> = Vec::with_capacity(65536);
@@ -128,7 +135,7 @@ impl TlsServer {
                         match rx.poll_read(&mut buffer) {
                             Ok(Async::Ready(n)) => {
                                 if n > 0 {
-                                    stm.lock().unwrap().process_stream(id_cloned as u64, &buffer[..n].to_vec());
+                                    stm.lock().unwrap().process_stream(id_cloned, &buffer[..n].to_vec());
                                 } else {
                                     info!("Client disconnected");
                                     *connected_cln.lock().unwrap() = false;
@@ -138,7 +145,9 @@ impl TlsServer {
                             _ => { *connected_cln.lock().unwrap() = false; }
                         };
                         Ok(())
-                    }).map_err(|e| error!("=>{}", e));
+                    })
+                    .map(move |_| stm_disconnect.lock().unwrap().disconnect_stream(id))
+                    .map_err(|e| error!("=>{}", e));
                     tokio::spawn(worker);
                     Ok(())
                 })
@@ -148,6 +157,16 @@ impl TlsServer {
                 Ok(())
             });
 
-        tokio::run(done.map_err(drop));
+        // Players have some time to reconnect before being removed
+        let cleanup = Interval::new_interval(std::time::Duration::from_secs(1))
+            .for_each(move |_| {
+                let mut stm = stm_cleanup.lock().unwrap();
+                stm.remove_expired_sessions();
+                stm.update_finished_matches();
+                Ok(())
+            })
+            .map_err(|e| error!("=>{}", e));
+
+        tokio::run(done.map_err(drop).join(cleanup).map(|_| ()));
     }
 }
\ No newline at end of file
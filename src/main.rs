@@ -40,18 +40,56 @@ extern crate tokio_rustls;
 
 pub mod bomber;
 
+const REPLAYS_DIR: &str = "./replays";
+
 use bomber::core::Server;
+use bomber::core::game::{Replay, ReplayPlayer};
+use bomber::gen::{MapAnalysis, MapFile};
 use bomber::net::{PlayerStreamManager, TlsServer, TlsServerConfig};
 
+use std::env;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 
 fn main() {
     // Init logging
     env_logger::init();
 
-    let server = Arc::new(Mutex::new(Server::new()));
+    // bomberust-server analyze <file.map>... prints the fairness of maps
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "analyze" {
+        for path in &args[2..] {
+            match MapFile::load(Path::new(path)) {
+                Ok(file) => println!("{} ({})\n{}\n", file.name, path, MapAnalysis::new(&file.map)),
+                Err(e) => println!("{}: {}\n", path, e),
+            }
+        }
+        return;
+    }
+
+    // bomberust-server replay <file.replay> plays a game again, one frame per second
+    if args.len() > 2 && args[1] == "replay" {
+        match Replay::load(Path::new(&args[2])) {
+            Ok(replay) => watch_replay(replay),
+            Err(e) => println!("{}: {}", args[2], e),
+        }
+        return;
+    }
+
+    // bomberust-server --replays [dir] saves the replay of every round
+    let mut server = Server::new();
+    if let Some(idx) = args.iter().position(|arg| arg == "--replays") {
+        let dir = match args.get(idx + 1) {
+            Some(dir) if !dir.starts_with("--") => dir.as_str(),
+            _ => REPLAYS_DIR,
+        };
+        info!("Replays are saved in {}", dir);
+        server.set_replays_dir(Some(PathBuf::from(dir)));
+    }
+    let server = Arc::new(Mutex::new(server));
     let streams_manager = Arc::new(Mutex::new(PlayerStreamManager::new(server)));
     let server_thread = thread::spawn(move || {
         // TODO get config from file
@@ -67,3 +105,20 @@ fn main() {
 
     let _ = server_thread.join();
 }
+
+/**
+ * Print the map of a replayed game at each second of game time
+ * @param replay    The replay to watch
+ */
+fn watch_replay(replay: Replay) {
+    let tick_rate = replay.tick_rate as u64;
+    let mut player = ReplayPlayer::new(replay);
+    while !player.finished() {
+        player.step();
+        if player.game.tick() % tick_rate == 0 {
+            println!("{}s\n{}\n", player.game.tick() / tick_rate, player.game.map);
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+    println!("Game over, winner: {:?}, winning team: {:?}", player.game.winner(), player.game.winning_team());
+}